@@ -80,6 +80,7 @@ async fn e2e() {
     // write some random genesis file
     let genesis = timestampvm::genesis::Genesis {
         data: random_manager::secure_string(10),
+        ..Default::default()
     };
     let genesis_file_path = random_manager::tmp_path(10, None).unwrap();
     genesis.sync(&genesis_file_path).unwrap();
@@ -0,0 +1,79 @@
+//! Implements operator-only handlers of the chain.
+//! To be served via `[HOST]/ext/bc/[CHAIN ID]/admin`, which can be kept
+//! behind a proxy separately from the public `rpc` path.
+
+use crate::{metrics, state::integrity, vm::Vm};
+use avalanche_types::subnet::rpc::snow::engine::common::appsender::AppSender;
+use jsonrpc_core::{BoxFuture, Error, ErrorCode, Result};
+use jsonrpc_derive::rpc;
+
+use super::chain_handlers::create_jsonrpc_error;
+
+/// Defines admin RPCs of the chain.
+#[rpc]
+pub trait Rpc {
+    /// Checks the stored chain from the last accepted block down to genesis,
+    /// and reports any inconsistency found.
+    #[rpc(name = "verifyChain", alias("timestampvm.verifyChain"))]
+    fn verify_chain(&self) -> BoxFuture<Result<integrity::Report>>;
+
+    /// Fetches the VM counters.
+    #[rpc(name = "getMetrics", alias("timestampvm.getMetrics"))]
+    fn get_metrics(&self) -> BoxFuture<Result<metrics::Snapshot>>;
+}
+
+/// Implements API services for the admin handlers.
+#[derive(Clone)]
+pub struct AdminService<A> {
+    pub vm: Vm<A>,
+}
+
+impl<A> AdminService<A> {
+    pub fn new(vm: Vm<A>) -> Self {
+        Self { vm }
+    }
+}
+
+impl<A> Rpc for AdminService<A>
+where
+    A: AppSender + Send + Sync + Clone + 'static,
+{
+    fn verify_chain(&self) -> BoxFuture<Result<integrity::Report>> {
+        log::info!("verify_chain called");
+        let vm = self.vm.clone();
+
+        Box::pin(async move {
+            // walks the whole chain, so the vm lock must not be held meanwhile
+            let state = vm.state.read().await.state.clone();
+            if let Some(state) = &state {
+                return integrity::verify_chain(state)
+                    .await
+                    .map_err(create_jsonrpc_error);
+            }
+
+            Err(Error {
+                code: ErrorCode::InternalError,
+                message: String::from("no state manager found"),
+                data: None,
+            })
+        })
+    }
+
+    fn get_metrics(&self) -> BoxFuture<Result<metrics::Snapshot>> {
+        log::debug!("get_metrics called");
+        let vm = self.vm.clone();
+
+        Box::pin(async move {
+            let vm_state = vm.state.read().await;
+            if let Some(state) = &vm_state.state {
+                return Ok(state.metrics.snapshot());
+            }
+
+            Err(Error {
+                code: ErrorCode::InternalError,
+                message: String::from("no state manager found"),
+                data: None,
+            })
+        })
+    }
+}
@@ -1,15 +1,29 @@
 //! Implements chain/VM specific handlers.
 //! To be served via `[HOST]/ext/bc/[CHAIN ID]/rpc`.
 
-use crate::{block::Block, vm::Vm};
-use avalanche_types::{ids, proto::http::Element, subnet::rpc::http::handle::Handle};
+use crate::{
+    block::{
+        signature::{ProposalSignature, Scheme},
+        Block,
+    },
+    mempool,
+    upgrade::{Rules, Upgrade},
+    vm::{ProposalStatus, Vm},
+};
+use avalanche_types::{
+    codec::serde::hex_0x_bytes::Hex0xBytes,
+    ids,
+    proto::http::Element,
+    subnet::rpc::{http::handle::Handle, snow::engine::common::appsender::AppSender},
+};
 use bytes::Bytes;
 use jsonrpc_core::{BoxFuture, Error, ErrorCode, IoHandler, Result};
 use jsonrpc_derive::rpc;
 use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr, PickFirst};
 use std::{borrow::Borrow, io, marker::PhantomData, str::FromStr};
 
-use super::de_request;
+use super::{admin_handlers, de_request};
 
 /// Defines RPCs specific to the chain.
 #[rpc]
@@ -29,18 +43,81 @@ pub trait Rpc {
     /// Fetches the block.
     #[rpc(name = "getBlock", alias("timestampvm.getBlock"))]
     fn get_block(&self, args: GetBlockArgs) -> BoxFuture<Result<GetBlockResponse>>;
+
+    /// Fetches the accepted block at the height.
+    #[rpc(name = "getBlockByHeight", alias("timestampvm.getBlockByHeight"))]
+    fn get_block_by_height(&self, args: GetBlockByHeightArgs) -> BoxFuture<Result<BlockWithId>>;
+
+    /// Fetches a page of accepted blocks in ascending height order.
+    #[rpc(name = "getBlocks", alias("timestampvm.getBlocks"))]
+    fn get_blocks(&self, args: GetBlocksArgs) -> BoxFuture<Result<GetBlocksResponse>>;
+
+    /// Fetches the status of a proposal by the Id returned from `proposeBlock`.
+    #[rpc(name = "getProposalStatus", alias("timestampvm.getProposalStatus"))]
+    fn get_proposal_status(
+        &self,
+        args: GetProposalStatusArgs,
+    ) -> BoxFuture<Result<GetProposalStatusResponse>>;
+
+    /// Fetches the Merkle proof that a proposal is part of a block,
+    /// by its index in the block or by the sha256 of its data.
+    #[rpc(name = "getInclusionProof", alias("timestampvm.getInclusionProof"))]
+    fn get_inclusion_proof(
+        &self,
+        args: GetInclusionProofArgs,
+    ) -> BoxFuture<Result<GetInclusionProofResponse>>;
+
+    /// Fetches the upgrade schedule and the rules the next block is built with.
+    #[rpc(name = "getUpgrades", alias("timestampvm.getUpgrades"))]
+    fn get_upgrades(&self) -> BoxFuture<Result<GetUpgradesResponse>>;
 }
 
+#[serde_as]
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct ProposeBlockArgs {
     #[serde(with = "avalanche_types::codec::serde::base64_bytes")]
     pub data: Vec<u8>,
+
+    /// Signs the proposal, together with `public_key` and `signature`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scheme: Option<Scheme>,
+    /// Compressed public key of the proposer.
+    #[serde_as(as = "Option<Hex0xBytes>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<Vec<u8>>,
+    /// Signature over the signing digest of the data for this chain,
+    /// see [`signing_digest`](crate::block::signature::signing_digest).
+    #[serde_as(as = "Option<Hex0xBytes>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<Vec<u8>>,
+}
+
+impl ProposeBlockArgs {
+    /// Returns the proposal signature, if any.
+    /// # Errors
+    /// Fails if only one of the public key and signature is set, or the public key is malformed.
+    pub fn proposal_signature(&self) -> io::Result<Option<ProposalSignature>> {
+        match (&self.public_key, &self.signature) {
+            (None, None) => Ok(None),
+            (Some(public_key), Some(signature)) => ProposalSignature::try_new(
+                self.scheme.unwrap_or_default(),
+                public_key.clone(),
+                signature.clone(),
+            )
+            .map(Some),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "public_key and signature must be set together",
+            )),
+        }
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct ProposeBlockResponse {
-    /// TODO: returns Id for later query, using hash + time?
     pub success: bool,
+    /// Proposal Id (sha256 of the data), to query with `getProposalStatus`.
+    pub id: ids::Id,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -50,8 +127,8 @@ pub struct LastAcceptedResponse {
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct GetBlockArgs {
-    /// TODO: use "ids::Id"
-    /// if we use "ids::Id", it fails with:
+    /// TODO: use `ids::Id`
+    /// if we use `ids::Id`, it fails with:
     /// "Invalid params: invalid type: string \"g25v3qDyAaHfR7kBev8tLUHouSgN5BJuZjy1BYS1oiHd2vres\", expected a borrowed string."
     pub id: String,
 }
@@ -61,6 +138,115 @@ pub struct GetBlockResponse {
     pub block: Block,
 }
 
+/// Heights are accepted as numbers or strings, as sent by the [`client`](crate::client).
+#[serde_as]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct GetBlockByHeightArgs {
+    #[serde_as(as = "PickFirst<(_, DisplayFromStr)>")]
+    pub height: u64,
+}
+
+/// Represents a block with its Id, which the block JSON does not include.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct BlockWithId {
+    pub id: ids::Id,
+    pub block: Block,
+}
+
+/// Limits how many blocks a single `getBlocks` call returns.
+pub const MAX_BLOCKS_PER_PAGE: u32 = 100;
+
+#[serde_as]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GetBlocksArgs {
+    #[serde_as(as = "PickFirst<(_, DisplayFromStr)>")]
+    pub start_height: u64,
+    /// Capped at [`MAX_BLOCKS_PER_PAGE`](MAX_BLOCKS_PER_PAGE), which is also the default.
+    #[serde_as(as = "Option<PickFirst<(_, DisplayFromStr)>>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GetBlocksResponse {
+    /// Accepted blocks in ascending height order.
+    pub blocks: Vec<BlockWithId>,
+    /// Height to start the next page from, if blocks were accepted past this page.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_height: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct GetProposalStatusArgs {
+    /// Same as [`GetBlockArgs`](GetBlockArgs), `ids::Id` fails to deserialize here.
+    pub id: String,
+}
+
+/// e.g., `{"id":"...","status":"accepted","blockId":"...","height":3}`
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct GetProposalStatusResponse {
+    pub id: ids::Id,
+    #[serde(flatten)]
+    pub status: ProposalStatus,
+}
+
+/// Exactly one of `entryIndex` and `dataHash` must be set.
+#[serde_as]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GetInclusionProofArgs {
+    /// Same as [`GetBlockArgs`](GetBlockArgs), `ids::Id` fails to deserialize here.
+    pub block_id: String,
+    /// Index of the proposal in the block, as a number or a string.
+    #[serde_as(as = "Option<PickFirst<(_, DisplayFromStr)>>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entry_index: Option<u32>,
+    /// sha256 of the proposed data, i.e., the proposal Id from `proposeBlock`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_hash: Option<String>,
+}
+
+/// Proves that a proposal is part of a block, given the proposed data,
+/// with [`verify_inclusion_proof`](crate::client::verify_inclusion_proof).
+/// The header fields let the verifier recompute the block Id from the Merkle root.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct GetInclusionProofResponse {
+    pub block_id: ids::Id,
+    pub parent_id: ids::Id,
+    pub height: u64,
+    pub timestamp: u64,
+    pub merkle_root: ids::Id,
+    pub entry_index: u32,
+    /// Number of proposals in the block.
+    pub entry_count: u32,
+    /// sha256 of the proposed data.
+    pub data_hash: ids::Id,
+    /// Signature of the proposer, part of the Merkle leaf if the proposal is signed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<ProposalSignature>,
+    /// Sibling hashes from the leaf up to the root.
+    pub path: Vec<ids::Id>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct UpgradeStatus {
+    #[serde(flatten)]
+    pub upgrade: Upgrade,
+    /// "true" if the next block built on the preferred block follows this upgrade.
+    pub active: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct GetUpgradesResponse {
+    /// Timestamp of the preferred block, which picks the active rules.
+    pub preferred_timestamp: u64,
+    pub rules: Rules,
+    pub upgrades: Vec<UpgradeStatus>,
+}
+
 /// Implements API services for the chain-specific handlers.
 #[derive(Clone)]
 pub struct ChainService<A> {
@@ -75,7 +261,7 @@ impl<A> ChainService<A> {
 
 impl<A> Rpc for ChainService<A>
 where
-    A: Send + Sync + Clone + 'static,
+    A: AppSender + Send + Sync + Clone + 'static,
 {
     fn ping(&self) -> BoxFuture<Result<crate::api::PingResponse>> {
         log::debug!("ping called");
@@ -87,10 +273,14 @@ where
         let vm = self.vm.clone();
 
         Box::pin(async move {
-            vm.propose_block(args.data)
+            let signature = args
+                .proposal_signature()
+                .map_err(|e| Error::invalid_params(e.to_string()))?;
+            let id = vm
+                .propose_block(args.data, signature)
                 .await
                 .map_err(create_jsonrpc_error)?;
-            Ok(ProposeBlockResponse { success: true })
+            Ok(ProposeBlockResponse { success: true, id })
         })
     }
 
@@ -141,6 +331,221 @@ where
             })
         })
     }
+
+    fn get_block_by_height(&self, args: GetBlockByHeightArgs) -> BoxFuture<Result<BlockWithId>> {
+        log::debug!("get_block_by_height called for {}", args.height);
+        let vm = self.vm.clone();
+
+        Box::pin(async move {
+            let vm_state = vm.state.read().await;
+            if let Some(state) = &vm_state.state {
+                let id = state
+                    .get_block_id_at_height(args.height)
+                    .await
+                    .map_err(create_jsonrpc_error)?;
+                let block = state.get_block(&id).await.map_err(create_jsonrpc_error)?;
+
+                return Ok(BlockWithId { id, block });
+            }
+
+            Err(Error {
+                code: ErrorCode::InternalError,
+                message: String::from("no state manager found"),
+                data: None,
+            })
+        })
+    }
+
+    fn get_blocks(&self, args: GetBlocksArgs) -> BoxFuture<Result<GetBlocksResponse>> {
+        log::debug!(
+            "get_blocks called from {} with limit {:?}",
+            args.start_height,
+            args.limit
+        );
+        let vm = self.vm.clone();
+
+        Box::pin(async move {
+            let limit = args
+                .limit
+                .unwrap_or(MAX_BLOCKS_PER_PAGE)
+                .min(MAX_BLOCKS_PER_PAGE);
+            if limit == 0 {
+                return Err(Error::invalid_params("limit must be positive"));
+            }
+
+            let vm_state = vm.state.read().await;
+            if let Some(state) = &vm_state.state {
+                let blocks = state
+                    .get_accepted_blocks(args.start_height, limit as usize)
+                    .await
+                    .map_err(create_jsonrpc_error)?;
+                let last_accepted = state
+                    .get_last_accepted_block_id()
+                    .await
+                    .map_err(create_jsonrpc_error)?;
+
+                let next_height = match blocks.last() {
+                    Some(blk) if blk.id() != last_accepted => Some(blk.height() + 1),
+                    _ => None,
+                };
+                let blocks = blocks
+                    .into_iter()
+                    .map(|block| BlockWithId {
+                        id: block.id(),
+                        block,
+                    })
+                    .collect();
+                return Ok(GetBlocksResponse {
+                    blocks,
+                    next_height,
+                });
+            }
+
+            Err(Error {
+                code: ErrorCode::InternalError,
+                message: String::from("no state manager found"),
+                data: None,
+            })
+        })
+    }
+
+    fn get_proposal_status(
+        &self,
+        args: GetProposalStatusArgs,
+    ) -> BoxFuture<Result<GetProposalStatusResponse>> {
+        log::debug!("get_proposal_status called for {}", args.id);
+        let vm = self.vm.clone();
+
+        Box::pin(async move {
+            let id = ids::Id::from_str(&args.id).map_err(|e| {
+                Error::invalid_params(format!("invalid proposal id '{}': {e}", args.id))
+            })?;
+            let status = vm
+                .get_proposal_status(&id)
+                .await
+                .map_err(create_jsonrpc_error)?;
+            Ok(GetProposalStatusResponse { id, status })
+        })
+    }
+
+    fn get_inclusion_proof(
+        &self,
+        args: GetInclusionProofArgs,
+    ) -> BoxFuture<Result<GetInclusionProofResponse>> {
+        log::debug!("get_inclusion_proof called for {}", args.block_id);
+        let vm = self.vm.clone();
+
+        Box::pin(async move {
+            let block_id = ids::Id::from_str(&args.block_id).map_err(|e| {
+                Error::invalid_params(format!("invalid block id '{}': {e}", args.block_id))
+            })?;
+
+            let vm_state = vm.state.read().await;
+            let Some(state) = &vm_state.state else {
+                return Err(Error {
+                    code: ErrorCode::InternalError,
+                    message: String::from("no state manager found"),
+                    data: None,
+                });
+            };
+            let block = state
+                .get_block(&block_id)
+                .await
+                .map_err(create_jsonrpc_error)?;
+            drop(vm_state);
+
+            if block.is_legacy_json() {
+                return Err(Error::invalid_params(format!(
+                    "block {block_id} is JSON-encoded and its Id does not cover a Merkle root"
+                )));
+            }
+
+            let entries = block.proposal_entries();
+            let index = match (args.entry_index, &args.data_hash) {
+                (Some(index), None) => index as usize,
+                (None, Some(data_hash)) => {
+                    let data_hash = ids::Id::from_str(data_hash).map_err(|e| {
+                        Error::invalid_params(format!("invalid data hash '{data_hash}': {e}"))
+                    })?;
+                    entries
+                        .iter()
+                        .position(|e| ids::Id::sha256(e.data()) == data_hash)
+                        .ok_or_else(|| {
+                            Error::invalid_params(format!(
+                                "no data with hash {data_hash} in block {block_id}"
+                            ))
+                        })?
+                }
+                _ => {
+                    return Err(Error::invalid_params(
+                        "exactly one of entryIndex and dataHash must be set",
+                    ))
+                }
+            };
+            let path = block
+                .inclusion_proof(index)
+                .map_err(create_jsonrpc_error)?
+                .ok_or_else(|| {
+                    Error::invalid_params(format!(
+                        "entry index {index} out of range for {} entries",
+                        entries.len()
+                    ))
+                })?;
+
+            let entry = &entries[index];
+            Ok(GetInclusionProofResponse {
+                block_id,
+                parent_id: block.parent_id(),
+                height: block.height(),
+                timestamp: block.timestamp(),
+                merkle_root: block.merkle_root(),
+                entry_index: u32::try_from(index)
+                    .map_err(|e| Error::invalid_params(e.to_string()))?,
+                entry_count: u32::try_from(entries.len())
+                    .map_err(|e| Error::invalid_params(e.to_string()))?,
+                data_hash: ids::Id::sha256(entry.data()),
+                signature: entry.signature().cloned(),
+                path,
+            })
+        })
+    }
+
+    fn get_upgrades(&self) -> BoxFuture<Result<GetUpgradesResponse>> {
+        log::debug!("get_upgrades called");
+        let vm = self.vm.clone();
+
+        Box::pin(async move {
+            let vm_state = vm.state.read().await;
+            if let Some(state) = &vm_state.state {
+                let prnt_blk = state
+                    .get_block(&vm_state.preferred)
+                    .await
+                    .map_err(create_jsonrpc_error)?;
+                let preferred_timestamp = prnt_blk.timestamp();
+
+                let schedule = &state.schedule;
+                let upgrades = schedule
+                    .upgrades
+                    .iter()
+                    .map(|u| UpgradeStatus {
+                        upgrade: u.clone(),
+                        active: u.activation_timestamp <= preferred_timestamp,
+                    })
+                    .collect();
+                return Ok(GetUpgradesResponse {
+                    preferred_timestamp,
+                    rules: schedule.rules_at(preferred_timestamp),
+                    upgrades,
+                });
+            }
+
+            Err(Error {
+                code: ErrorCode::InternalError,
+                message: String::from("no state manager found"),
+                data: None,
+            })
+        })
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -160,6 +565,19 @@ impl<T: Rpc> ChainHandler<T> {
     }
 }
 
+impl<T> ChainHandler<T> {
+    /// Creates the handler of the admin APIs, which shares the handler type of the
+    /// chain APIs since a VM serves all its chain handlers with a single type.
+    pub fn new_admin<S: admin_handlers::Rpc>(service: S) -> Self {
+        let mut handler = jsonrpc_core::IoHandler::new();
+        handler.extend_with(admin_handlers::Rpc::to_delegate(service));
+        Self {
+            handler,
+            _marker: PhantomData,
+        }
+    }
+}
+
 #[tonic::async_trait]
 impl<T> Handle for ChainHandler<T>
 where
@@ -180,9 +598,24 @@ where
     }
 }
 
-fn create_jsonrpc_error<E: Borrow<std::io::Error>>(e: E) -> Error {
+/// JSON-RPC error code returned when the mempool can't take more proposals.
+pub const MEMPOOL_FULL_ERROR_CODE: i64 = -32001;
+
+/// JSON-RPC error code returned when the proposed data is already pending,
+/// or a verified or accepted block already carries it.
+pub const MEMPOOL_DUPLICATE_ERROR_CODE: i64 = -32002;
+
+pub(crate) fn create_jsonrpc_error<E: Borrow<std::io::Error>>(e: E) -> Error {
     let e = e.borrow();
-    let mut error = Error::new(ErrorCode::InternalError);
+    let code = match mempool::error_of(e) {
+        Some(mempool::Error::Full) => ErrorCode::ServerError(MEMPOOL_FULL_ERROR_CODE),
+        Some(mempool::Error::Duplicate) => ErrorCode::ServerError(MEMPOOL_DUPLICATE_ERROR_CODE),
+        None if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            ErrorCode::ServerError(MEMPOOL_DUPLICATE_ERROR_CODE)
+        }
+        None => ErrorCode::InternalError,
+    };
+    let mut error = Error::new(code);
     error.message = format!("{e}");
     error
 }
@@ -1,6 +1,7 @@
 //! Implementation of timestampvm APIs, to be registered via
 //! `create_static_handlers` and `create_handlers` in the [`vm`](crate::vm) crate.
 
+pub mod admin_handlers;
 pub mod chain_handlers;
 pub mod static_handlers;
 
@@ -0,0 +1,443 @@
+//! Exports the accepted chain into a portable archive, and imports it back.
+//!
+//! An archive is a [`Header`](Header) followed by the encoded accepted blocks in
+//! ascending height order, each prefixed by its `u32` big-endian length:
+//! `magic (4 bytes) | archive version (u16) | genesis hash (32 bytes) |
+//! start height (u64) | end height (u64) | blocks (u32 length + block bytes)...`.
+//!
+//! The genesis hash is the sha256 of the genesis bytes the chain was created with
+//! (see [`State::get_genesis_hash`](crate::state::State::get_genesis_hash)), so
+//! that an archive is never imported into a different chain.
+
+use std::io::{self, Error, ErrorKind, Read, Write};
+
+use crate::{block::Block, codec, state::State};
+use avalanche_types::{ids, packer::Packer};
+
+/// Identifies timestampvm archives.
+pub const MAGIC: &[u8; 4] = b"TSVA";
+
+/// Current archive version.
+pub const VERSION: u16 = 0;
+
+/// Length of the encoded [`Header`](Header).
+pub const HEADER_LEN: usize = MAGIC.len() + 2 + ids::LEN + 8 + 8;
+
+/// Describes the blocks in an archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub genesis_hash: ids::Id,
+    /// Height of the first block, zero for archives written by [`export`](export).
+    pub start_height: u64,
+    /// Height of the last block, the last accepted block at export.
+    pub end_height: u64,
+}
+
+impl Header {
+    /// Returns the number of blocks in the archive.
+    #[must_use]
+    pub fn block_count(&self) -> u64 {
+        self.end_height - self.start_height + 1
+    }
+
+    /// Encodes the header.
+    /// # Errors
+    /// Fails if the header can't be packed.
+    pub fn encode(&self) -> io::Result<Vec<u8>> {
+        let packer = Packer::new(HEADER_LEN, HEADER_LEN);
+        packer.pack_bytes(MAGIC).map_err(codec::to_io_error)?;
+        packer.pack_u16(VERSION).map_err(codec::to_io_error)?;
+        packer
+            .pack_bytes(self.genesis_hash.as_ref())
+            .map_err(codec::to_io_error)?;
+        packer
+            .pack_u64(self.start_height)
+            .map_err(codec::to_io_error)?;
+        packer
+            .pack_u64(self.end_height)
+            .map_err(codec::to_io_error)?;
+        Ok(packer.take_bytes().to_vec())
+    }
+
+    /// Decodes the header.
+    /// # Errors
+    /// Fails if the bytes are not a supported archive header.
+    pub fn decode(d: &[u8]) -> io::Result<Self> {
+        let packer = Packer::load_bytes_for_unpack(d.len(), d);
+        let magic = packer
+            .unpack_bytes(MAGIC.len())
+            .map_err(codec::to_io_error)?;
+        if magic != MAGIC {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "not a timestampvm archive",
+            ));
+        }
+        let version = packer.unpack_u16().map_err(codec::to_io_error)?;
+        if version != VERSION {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("unsupported archive version {version}, expected {VERSION}"),
+            ));
+        }
+        let genesis_hash = packer.unpack_bytes(ids::LEN).map_err(codec::to_io_error)?;
+        let start_height = packer.unpack_u64().map_err(codec::to_io_error)?;
+        let end_height = packer.unpack_u64().map_err(codec::to_io_error)?;
+        codec::ensure_consumed(&packer)?;
+        if start_height > end_height {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("archive start height {start_height} > end height {end_height}"),
+            ));
+        }
+
+        Ok(Self {
+            genesis_hash: ids::Id::from_slice(&genesis_hash),
+            start_height,
+            end_height,
+        })
+    }
+}
+
+/// Writes the accepted chain to the writer, walking from the last accepted block
+/// down to genesis.
+/// # Errors
+/// Fails if nothing has been accepted, the genesis hash is not recorded,
+/// a block can't be read or the writer fails.
+pub async fn export(state: &State, w: &mut impl Write) -> io::Result<Header> {
+    if !state.has_last_accepted_block().await? {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            "no accepted block to export",
+        ));
+    }
+    let genesis_hash = state.get_genesis_hash().await?.ok_or_else(|| {
+        Error::new(
+            ErrorKind::NotFound,
+            "genesis hash is not recorded, restart the node once to record it",
+        )
+    })?;
+
+    let last_accepted = state
+        .get_block(&state.get_last_accepted_block_id().await?)
+        .await?;
+
+    // collect the Ids first, blocks are written from the lowest height upwards
+    let mut blk_ids = vec![last_accepted.id()];
+    let mut blk = last_accepted.clone();
+    while blk.height() > 0 {
+        blk = state.get_block(&blk.parent_id()).await?;
+        blk_ids.push(blk.id());
+    }
+    blk_ids.reverse();
+
+    let header = Header {
+        genesis_hash,
+        start_height: 0,
+        end_height: last_accepted.height(),
+    };
+    w.write_all(&header.encode()?)?;
+    for blk_id in &blk_ids {
+        let blk = state.get_block(blk_id).await?;
+        let len = u32::try_from(blk.bytes().len()).map_err(|_| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("block {blk_id} is too large to archive"),
+            )
+        })?;
+        w.write_all(&len.to_be_bytes())?;
+        w.write_all(blk.bytes())?;
+    }
+    w.flush()?;
+
+    log::info!(
+        "exported {} block(s) from height {} to {}",
+        header.block_count(),
+        header.start_height,
+        header.end_height
+    );
+    Ok(header)
+}
+
+/// Summarizes an [`import`](import).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImportSummary {
+    pub header: Header,
+    /// Number of blocks verified and accepted.
+    pub imported: u64,
+    /// Number of blocks the state had already accepted.
+    pub skipped: u64,
+}
+
+/// Reads an archive and accepts its blocks into the state, in order.
+/// The state must have been initialized with the genesis of the archive, which records
+/// its hash. Blocks the state already accepted at the same height are skipped, and every
+/// other block must extend the last accepted block and pass [`Block::verify`](Block::verify)
+/// with the rules of the state.
+/// # Errors
+/// Fails if the state has no recorded genesis hash, if the archive is malformed, is for
+/// another genesis, declares a block larger than any block size limit, does not extend
+/// the accepted chain of the state, or a block fails verification.
+/// Blocks accepted before the failure stay accepted.
+pub async fn import(state: &mut State, r: &mut impl Read) -> io::Result<ImportSummary> {
+    let mut header_bytes = [0; HEADER_LEN];
+    r.read_exact(&mut header_bytes)?;
+    let header = Header::decode(&header_bytes)?;
+
+    // the header is not trusted to pick the chain, it is only checked against it
+    let genesis_hash = state.get_genesis_hash().await?.ok_or_else(|| {
+        Error::new(
+            ErrorKind::NotFound,
+            "genesis hash is not recorded, initialize the chain before importing",
+        )
+    })?;
+    if genesis_hash != header.genesis_hash {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "archive genesis hash {} does not match the state genesis hash {genesis_hash}",
+                header.genesis_hash
+            ),
+        ));
+    }
+    let max_block_size = state.schedule.max_block_size_limit();
+
+    let mut summary = ImportSummary {
+        header,
+        imported: 0,
+        skipped: 0,
+    };
+    for height in header.start_height..=header.end_height {
+        let mut len = [0; 4];
+        r.read_exact(&mut len).map_err(|e| {
+            Error::new(
+                e.kind(),
+                format!("archive ends before the block at height {height}: {e}"),
+            )
+        })?;
+        let len = u32::from_be_bytes(len) as usize;
+        if len > max_block_size {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("block at height {height} declares {len} bytes, above the block size limit {max_block_size}"),
+            ));
+        }
+        let mut blk_bytes = vec![0; len];
+        r.read_exact(&mut blk_bytes)?;
+
+        let mut blk = Block::from_slice(&blk_bytes)?;
+        if blk.height() != height {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("expected block at height {height}, found {}", blk.height()),
+            ));
+        }
+
+        if let Ok(blk_id) = state.get_block_id_at_height(height).await {
+            if blk_id != blk.id() {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "block {} conflicts with the accepted block {blk_id} at height {height}",
+                        blk.id()
+                    ),
+                ));
+            }
+            summary.skipped += 1;
+            continue;
+        }
+
+        let has_last_accepted = state.has_last_accepted_block().await?;
+        if has_last_accepted && blk.parent_id() != state.get_last_accepted_block_id().await? {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "block {} at height {height} does not extend the last accepted block",
+                    blk.id()
+                ),
+            ));
+        }
+        if !has_last_accepted && height != 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("archive starts at height {height}, but the state has no accepted block"),
+            ));
+        }
+
+        blk.set_state(state.clone());
+        blk.verify().await?;
+        blk.accept().await?;
+        summary.imported += 1;
+    }
+
+    let mut trailing = [0; 1];
+    if r.read(&mut trailing)? > 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "trailing bytes after the last archived block",
+        ));
+    }
+
+    log::info!(
+        "imported {} block(s), skipped {} already accepted block(s)",
+        summary.imported,
+        summary.skipped
+    );
+    Ok(summary)
+}
+
+/// `RUST_LOG=debug cargo test --package timestampvm --lib -- archive::test_archive --exact --show-output`
+#[tokio::test]
+#[allow(clippy::too_many_lines)]
+async fn test_archive() {
+    use avalanche_types::choices;
+
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let mut state = State::default();
+    let genesis_hash = ids::Id::sha256(b"genesis");
+    state.put_genesis_hash(&genesis_hash).await.unwrap();
+
+    let mut blk = Block::try_new(
+        ids::Id::empty(),
+        0,
+        0,
+        b"genesis".to_vec(),
+        choices::status::Status::default(),
+    )
+    .unwrap();
+    blk.set_state(state.clone());
+    blk.accept().await.unwrap();
+    for i in 1..=5_u64 {
+        blk = Block::try_new(
+            blk.id(),
+            i,
+            i,
+            vec![u8::try_from(i).unwrap()],
+            choices::status::Status::default(),
+        )
+        .unwrap();
+        blk.set_state(state.clone());
+        blk.accept().await.unwrap();
+    }
+
+    let mut archive = Vec::new();
+    let header = export(&state, &mut archive).await.unwrap();
+    assert_eq!(
+        header,
+        Header {
+            genesis_hash,
+            start_height: 0,
+            end_height: 5,
+        }
+    );
+    assert_eq!(Header::decode(&archive[..HEADER_LEN]).unwrap(), header);
+
+    // restores into a fresh state of the same chain
+    let mut restored = State::default();
+    assert!(import(&mut restored, &mut archive.as_slice())
+        .await
+        .unwrap_err()
+        .to_string()
+        .contains("genesis hash is not recorded"));
+    assert!(restored.get_genesis_hash().await.unwrap().is_none());
+    restored.put_genesis_hash(&genesis_hash).await.unwrap();
+    let summary = import(&mut restored, &mut archive.as_slice())
+        .await
+        .unwrap();
+    assert_eq!(summary.imported, 6);
+    assert_eq!(
+        restored.get_last_accepted_block_id().await.unwrap(),
+        blk.id()
+    );
+    assert_eq!(
+        restored.get_genesis_hash().await.unwrap(),
+        Some(genesis_hash)
+    );
+
+    // importing again only skips, e.g., into a node that accepted its own genesis
+    let summary = import(&mut restored, &mut archive.as_slice())
+        .await
+        .unwrap();
+    assert_eq!((summary.imported, summary.skipped), (0, 6));
+
+    // other genesis
+    let mut other = State::default();
+    other
+        .put_genesis_hash(&ids::Id::sha256(b"other"))
+        .await
+        .unwrap();
+    assert!(import(&mut other, &mut archive.as_slice()).await.is_err());
+
+    // truncated, or with trailing bytes
+    let fresh_state = || async {
+        let mut fresh = State::default();
+        fresh.put_genesis_hash(&genesis_hash).await.unwrap();
+        fresh
+    };
+    let mut fresh = fresh_state().await;
+    assert!(import(&mut fresh, &mut &archive[..archive.len() - 1])
+        .await
+        .is_err());
+    let mut fresh = fresh_state().await;
+    let mut extended = archive.clone();
+    extended.push(0);
+    assert!(import(&mut fresh, &mut extended.as_slice()).await.is_err());
+
+    // a declared block length above the limit fails before allocating
+    let mut oversized = archive[..HEADER_LEN].to_vec();
+    oversized.extend(u32::MAX.to_be_bytes());
+    let mut fresh = fresh_state().await;
+    assert!(import(&mut fresh, &mut oversized.as_slice())
+        .await
+        .unwrap_err()
+        .to_string()
+        .contains("above the block size limit"));
+
+    // a block that fails verification, its timestamp is before its parent's
+    let parent = Block::try_new(
+        ids::Id::empty(),
+        0,
+        10,
+        b"genesis".to_vec(),
+        choices::status::Status::default(),
+    )
+    .unwrap();
+    let bad = Block::try_new(
+        parent.id(),
+        1,
+        0,
+        vec![1],
+        choices::status::Status::default(),
+    )
+    .unwrap();
+    let mut tampered = Vec::new();
+    tampered.extend(
+        Header {
+            genesis_hash,
+            start_height: 0,
+            end_height: 1,
+        }
+        .encode()
+        .unwrap(),
+    );
+    for b in [&parent, &bad] {
+        tampered.extend(u32::try_from(b.bytes().len()).unwrap().to_be_bytes());
+        tampered.extend(b.bytes());
+    }
+    let mut fresh = fresh_state().await;
+    let err = import(&mut fresh, &mut tampered.as_slice())
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("timestamp"), "{err}");
+    assert_eq!(
+        fresh.get_last_accepted_block_id().await.unwrap(),
+        parent.id()
+    );
+
+    // not an archive
+    assert!(Header::decode(&[0; HEADER_LEN]).is_err());
+}
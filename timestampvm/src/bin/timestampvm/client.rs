@@ -0,0 +1,271 @@
+use std::{
+    io::{self, Read},
+    time::Duration,
+};
+
+use avalanche_types::ids;
+use clap::{arg, value_parser, ArgAction, ArgMatches, Command};
+use serde::Serialize;
+use timestampvm::{
+    api::chain_handlers::BlockWithId,
+    block::Block,
+    client::{RetryPolicy, TimestampVmClient},
+};
+
+pub const NAME: &str = "client";
+
+pub const PING: &str = "ping";
+pub const PROPOSE: &str = "propose";
+pub const LAST_ACCEPTED: &str = "last-accepted";
+pub const GET_BLOCK: &str = "get-block";
+pub const TAIL: &str = "tail";
+
+#[must_use]
+pub fn command() -> Command {
+    Command::new(NAME)
+        .about("Calls the APIs of a running timestampvm chain")
+        .arg(
+            arg!(--endpoint <URL> "Node endpoint, HTTPS if the scheme is https")
+                .global(true)
+                .default_value("http://127.0.0.1:9650"),
+        )
+        .arg(
+            arg!(--chain <CHAIN> "Blockchain Id or alias")
+                .global(true)
+                .default_value("timestampvm"),
+        )
+        .arg(
+            arg!(--output <FORMAT> "Output format")
+                .global(true)
+                .value_parser(["human", "json"])
+                .default_value("human"),
+        )
+        .arg(
+            arg!(--"timeout-seconds" <SECONDS> "Time limit of each request")
+                .global(true)
+                .value_parser(value_parser!(u64))
+                .default_value("15"),
+        )
+        .arg(
+            arg!(--retries <COUNT> "Retries of requests that failed to reach the node")
+                .global(true)
+                .value_parser(value_parser!(u32))
+                .default_value("3"),
+        )
+        .arg(
+            arg!(--header <NAME_VALUE> "Header added to each request, as NAME:VALUE (repeatable)")
+                .global(true)
+                .action(ArgAction::Append),
+        )
+        .subcommand(Command::new(PING).about("Pings the VM"))
+        .subcommand(
+            Command::new(PROPOSE)
+                .about("Proposes data for the next block")
+                .arg(arg!([DATA] "Data to propose, as a string"))
+                .arg(
+                    arg!(--file <PATH> "Proposes the contents of the file, or of stdin if \"-\"")
+                        .conflicts_with("DATA"),
+                )
+                .group(
+                    clap::ArgGroup::new("input")
+                        .args(["DATA", "file"])
+                        .required(true),
+                ),
+        )
+        .subcommand(Command::new(LAST_ACCEPTED).about("Fetches the last accepted block"))
+        .subcommand(
+            Command::new(GET_BLOCK)
+                .about("Fetches a block by Id, or an accepted block by height")
+                .arg(arg!([ID] "Block Id"))
+                .arg(
+                    arg!(--height <HEIGHT> "Height of the accepted block")
+                        .value_parser(value_parser!(u64))
+                        .conflicts_with("ID"),
+                )
+                .group(
+                    clap::ArgGroup::new("block")
+                        .args(["ID", "height"])
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            Command::new(TAIL)
+                .about("Prints accepted blocks as they are accepted")
+                .arg(
+                    arg!(--"from-height" <HEIGHT> "First height to print, the next block if unset")
+                        .value_parser(value_parser!(u64)),
+                )
+                .arg(
+                    arg!(--"interval-ms" <MILLISECONDS> "Wait between polls once caught up")
+                        .value_parser(value_parser!(u64))
+                        .default_value("1000"),
+                ),
+        )
+        .subcommand_required(true)
+        .arg_required_else_help(true)
+}
+
+/// Runs the client subcommand.
+/// # Errors
+/// Fails if the arguments are invalid or the call fails.
+pub async fn execute(matches: &ArgMatches) -> io::Result<()> {
+    let client = build_client(matches)?;
+    let json = matches.get_one::<String>("output").map(String::as_str) == Some("json");
+
+    match matches.subcommand() {
+        Some((PING, _)) => {
+            let resp = client.ping().await?;
+            if json {
+                print_json(&resp)?;
+            } else {
+                println!("success: {}", resp.success);
+            }
+        }
+
+        Some((PROPOSE, sub_matches)) => {
+            let data = match sub_matches.get_one::<String>("file") {
+                Some(path) if path == "-" => {
+                    let mut d = Vec::new();
+                    io::stdin().read_to_end(&mut d)?;
+                    d
+                }
+                Some(path) => std::fs::read(path).map_err(|e| {
+                    io::Error::new(e.kind(), format!("failed to read '{path}' {e}"))
+                })?,
+                None => sub_matches
+                    .get_one::<String>("DATA")
+                    .expect("required")
+                    .as_bytes()
+                    .to_vec(),
+            };
+            let resp = client.propose_block(data).await?;
+            if json {
+                print_json(&resp)?;
+            } else {
+                println!("proposed {}", resp.id);
+            }
+        }
+
+        Some((LAST_ACCEPTED, _)) => {
+            let id = client.last_accepted().await?;
+            let block = client.get_block(&id).await?.block;
+            print_block(&BlockWithId { id, block }, json)?;
+        }
+
+        Some((GET_BLOCK, sub_matches)) => {
+            let blk = if let Some(height) = sub_matches.get_one::<u64>("height") {
+                client.get_block_by_height(*height).await?
+            } else {
+                let id = sub_matches
+                    .get_one::<String>("ID")
+                    .expect("required")
+                    .parse::<ids::Id>()
+                    .map_err(|e| {
+                        io::Error::new(io::ErrorKind::InvalidInput, format!("invalid block Id {e}"))
+                    })?;
+                let block = client.get_block(&id).await?.block;
+                BlockWithId { id, block }
+            };
+            print_block(&blk, json)?;
+        }
+
+        Some((TAIL, sub_matches)) => {
+            let interval =
+                Duration::from_millis(*sub_matches.get_one::<u64>("interval-ms").expect("default"));
+            let mut height = if let Some(height) = sub_matches.get_one::<u64>("from-height") {
+                *height
+            } else {
+                let id = client.last_accepted().await?;
+                client.get_block(&id).await?.block.height() + 1
+            };
+
+            loop {
+                let resp = client.get_blocks(height, None).await?;
+                for blk in &resp.blocks {
+                    print_block(blk, json)?;
+                    height = blk.block.height() + 1;
+                }
+                if resp.next_height.is_none() {
+                    tokio::time::sleep(interval).await;
+                }
+            }
+        }
+
+        _ => unreachable!("subcommand is required"),
+    }
+    Ok(())
+}
+
+fn build_client(matches: &ArgMatches) -> io::Result<TimestampVmClient> {
+    let endpoint = matches.get_one::<String>("endpoint").expect("default");
+    let chain = matches.get_one::<String>("chain").expect("default");
+    let timeout = *matches.get_one::<u64>("timeout-seconds").expect("default");
+    let retries = *matches.get_one::<u32>("retries").expect("default");
+
+    let mut builder = TimestampVmClient::builder(endpoint, chain)
+        .timeout(Duration::from_secs(timeout))
+        .retry(RetryPolicy {
+            max_retries: retries,
+            ..Default::default()
+        });
+    for header in matches.get_many::<String>("header").unwrap_or_default() {
+        let (name, value) = header.split_once(':').ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("header '{header}' is not NAME:VALUE"),
+            )
+        })?;
+        builder = builder.header(name.trim(), value.trim());
+    }
+    builder.build()
+}
+
+fn print_json(v: &impl Serialize) -> io::Result<()> {
+    let s = serde_json::to_string(v).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::Other,
+            format!("failed to serialize output {e}"),
+        )
+    })?;
+    println!("{s}");
+    Ok(())
+}
+
+fn print_block(blk: &BlockWithId, json: bool) -> io::Result<()> {
+    if json {
+        return print_json(blk);
+    }
+
+    let block: &Block = &blk.block;
+    println!(
+        "block {} height {} timestamp {} parent {}",
+        blk.id,
+        block.height(),
+        block.timestamp(),
+        block.parent_id()
+    );
+    if block.entries().is_empty() {
+        println!("  data {}", display_data(block.data()));
+    }
+    for (i, entry) in block.entries().iter().enumerate() {
+        match entry.proposer() {
+            Some(proposer) => println!(
+                "  entry {i} {} (proposer {proposer})",
+                display_data(entry.data())
+            ),
+            None => println!("  entry {i} {}", display_data(entry.data())),
+        }
+    }
+    Ok(())
+}
+
+/// Shows the data as a quoted string if it is printable text, as 0x-prefixed hex otherwise.
+fn display_data(d: &[u8]) -> String {
+    match std::str::from_utf8(d) {
+        Ok(s) if !s.chars().any(char::is_control) => format!("{s:?}"),
+        _ => {
+            let hex: String = d.iter().map(|b| format!("{b:02x}")).collect();
+            format!("0x{hex}")
+        }
+    }
+}
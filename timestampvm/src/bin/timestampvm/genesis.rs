@@ -1,4 +1,4 @@
-use clap::{arg, Command};
+use clap::{arg, value_parser, ArgAction, Command};
 
 pub const NAME: &str = "genesis";
 
@@ -6,6 +6,20 @@ pub const NAME: &str = "genesis";
 pub fn command() -> Command {
     Command::new(NAME)
         .about("Write a genesis file")
-        .arg(arg!(<DATA> "Genesis message data"))
+        .arg(arg!([DATA] "Genesis message data"))
+        .arg(
+            arg!(--timestamp <SECONDS> "Unix second of the genesis block")
+                .value_parser(value_parser!(u64))
+                .default_value("0"),
+        )
+        .arg(
+            arg!(--entry <BASE64> "Base64-encoded seed payload, instead of DATA")
+                .action(ArgAction::Append)
+                .conflicts_with("DATA"),
+        )
+        .arg(
+            arg!(--"allowed-proposer" <ADDRESS> "Short address of a key allowed to sign proposals (repeatable, anyone if unset)")
+                .action(ArgAction::Append),
+        )
         .arg_required_else_help(true)
 }
@@ -1,9 +1,10 @@
+pub mod client;
 pub mod genesis;
 pub mod vm_id;
 
 use std::io;
 
-use avalanche_types::subnet;
+use avalanche_types::{ids, subnet};
 use clap::{crate_version, Command};
 use timestampvm::vm;
 use tokio::sync::broadcast::{self, Receiver, Sender};
@@ -15,7 +16,11 @@ async fn main() -> io::Result<()> {
     let matches = Command::new(APP_NAME)
         .version(crate_version!())
         .about("Timestamp Vm")
-        .subcommands(vec![genesis::command(), vm_id::command()])
+        .subcommands(vec![
+            client::command(),
+            genesis::command(),
+            vm_id::command(),
+        ])
         .get_matches();
 
     // ref. https://github.com/env-logger-rs/env_logger/issues/47
@@ -24,9 +29,53 @@ async fn main() -> io::Result<()> {
     );
 
     match matches.subcommand() {
+        Some((client::NAME, sub_matches)) => client::execute(sub_matches).await,
+
         Some((genesis::NAME, sub_matches)) => {
-            let data = sub_matches.get_one::<String>("DATA").expect("required");
-            let genesis = timestampvm::genesis::Genesis { data: data.clone() };
+            let data = sub_matches
+                .get_one::<String>("DATA")
+                .cloned()
+                .unwrap_or_default();
+            let timestamp = *sub_matches.get_one::<u64>("timestamp").expect("default");
+            let entries = sub_matches
+                .get_many::<String>("entry")
+                .unwrap_or_default()
+                .map(|e| {
+                    base64::Engine::decode(&base64::engine::general_purpose::STANDARD, e).map_err(
+                        |e| {
+                            io::Error::new(
+                                io::ErrorKind::InvalidInput,
+                                format!("invalid entry {e}"),
+                            )
+                        },
+                    )
+                })
+                .collect::<io::Result<Vec<_>>>()?;
+            let allowed_proposers = sub_matches
+                .get_many::<String>("allowed-proposer")
+                .map(|addrs| {
+                    addrs
+                        .map(|a| {
+                            a.parse::<ids::short::Id>().map_err(|e| {
+                                io::Error::new(
+                                    io::ErrorKind::InvalidInput,
+                                    format!("invalid allowed proposer '{a}' {e}"),
+                                )
+                            })
+                        })
+                        .collect::<io::Result<Vec<_>>>()
+                })
+                .transpose()?;
+
+            let genesis = timestampvm::genesis::Genesis {
+                data,
+                timestamp,
+                entries,
+                params: timestampvm::upgrade::RuleOverrides {
+                    allowed_proposers,
+                    ..Default::default()
+                },
+            };
             println!("{genesis}");
 
             Ok(())
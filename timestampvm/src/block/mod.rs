@@ -1,23 +1,148 @@
 //! Implementation of [`snowman.Block`](https://pkg.go.dev/github.com/ava-labs/avalanchego/snow/consensus/snowman#Block) interface for timestampvm.
 
+pub mod signature;
+
 use std::{
     fmt,
     io::{self, Error, ErrorKind},
 };
 
-use crate::state;
+use crate::{codec, merkle, metrics::Metrics, state, upgrade};
 use avalanche_types::{
     choices,
     codec::serde::hex_0x_bytes::Hex0xBytes,
     ids,
+    packer::{self, Packer},
     subnet::rpc::consensus::snowman::{self, Decidable},
 };
-use chrono::{Duration, Utc};
+use chrono::Utc;
 use derivative::{self, Derivative};
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
+use signature::ProposalSignature;
+
+/// Default limit on the encoded size of a block.
+pub const DEFAULT_BLOCK_SIZE_LIMIT: usize = 4 * 1024 * 1024;
+
+/// Size of the encoded block prefix the block Id is the sha256 of:
+/// codec version, parent Id, height, timestamp and Merkle root.
+pub const ID_PREIMAGE_LEN: usize = packer::U16_LEN + 2 * ids::LEN + 2 * packer::U64_LEN;
+
+/// Size of an encoded block without any data or entries.
+pub const ENCODED_HEADER_LEN: usize = ID_PREIMAGE_LEN + 2 * packer::U32_LEN;
+
+/// Represents a single proposal packed into a [`Block`](Block).
+#[serde_as]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Default)]
+pub struct Entry {
+    /// Arbitrary data.
+    #[serde_as(as = "Hex0xBytes")]
+    data: Vec<u8>,
+    /// Optional signature of the proposer over the data.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    signature: Option<ProposalSignature>,
+}
+
+impl Entry {
+    #[must_use]
+    pub fn new(data: Vec<u8>) -> Self {
+        Self {
+            data,
+            signature: None,
+        }
+    }
+
+    /// Creates an entry signed by its proposer.
+    #[must_use]
+    pub fn new_signed(data: Vec<u8>, signature: ProposalSignature) -> Self {
+        Self {
+            data,
+            signature: Some(signature),
+        }
+    }
+
+    /// Returns the data of this entry.
+    #[must_use]
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the signature of this entry, if signed.
+    #[must_use]
+    pub fn signature(&self) -> Option<&ProposalSignature> {
+        self.signature.as_ref()
+    }
+
+    /// Returns the address of the proposer, if signed.
+    #[must_use]
+    pub fn proposer(&self) -> Option<ids::short::Id> {
+        self.signature.as_ref().map(|sig| sig.proposer.clone())
+    }
+
+    /// Checks the proposer signature for the chain, if any.
+    /// # Errors
+    /// Fails if the entry is signed and the signature is invalid.
+    pub fn verify_signature(&self, chain_id: &ids::Id) -> io::Result<()> {
+        match &self.signature {
+            Some(sig) => sig.verify(chain_id, &self.data),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the Merkle leaf of this entry, the hash of its encoding
+    /// so that the block Id also covers the proposer signature.
+    /// # Errors
+    /// Fails if the entry can't be encoded.
+    pub fn leaf_hash(&self) -> io::Result<ids::Id> {
+        let packer = Packer::new(codec::MAX_SIZE, self.encoded_len());
+        self.pack(&packer)?;
+        Ok(merkle::leaf_hash(&packer.take_bytes()))
+    }
+
+    fn pack(&self, packer: &Packer) -> io::Result<()> {
+        packer
+            .pack_bytes_with_header(&self.data)
+            .map_err(codec::to_io_error)?;
+        match &self.signature {
+            Some(sig) => sig.pack(packer),
+            None => packer
+                .pack_byte(signature::UNSIGNED_TYPE_ID)
+                .map_err(codec::to_io_error),
+        }
+    }
+
+    /// Returns the number of bytes this entry adds to an encoded block.
+    #[must_use]
+    pub fn encoded_len(&self) -> usize {
+        packer::U32_LEN
+            + self.data.len()
+            + signature::TYPE_ID_LEN
+            + self
+                .signature
+                .as_ref()
+                .map_or(0, ProposalSignature::encoded_len)
+    }
+}
 
 /// Represents a block, specific to [`Vm`](crate::vm::Vm).
+///
+/// Blocks are encoded with the [`codec`](crate::codec) as:
+/// `codec version (u16) | parent_id (32 bytes) | height (u64) | timestamp (u64) |
+/// merkle_root (32 bytes) | data (u32 length + bytes) | entries (u32 count + each entry)`,
+/// where each entry is
+/// `data (u32 length + bytes) | scheme (u8, 0 if unsigned) | public key | signature | proposer (20 bytes)`.
+/// Binary blocks carry at least one entry and leave the single data empty,
+/// so that the same proposals can't be encoded, and thus identified, in two ways.
+///
+/// The block Id is the sha256 of the encoding up to the Merkle root (see
+/// [`compute_id`](Block::compute_id)), and the root commits to the data or entries,
+/// so that a proposal can be proven part of a block without the whole block.
+///
+/// Until the [`binary_blocks`](crate::upgrade::Rules::binary_blocks) rule activates,
+/// blocks carry a single proposal and keep the JSON encoding of the earlier versions
+/// (see [`try_new`](Block::try_new)), so that nodes without the binary codec can
+/// still follow the chain. Those blocks keep the sha256 of their bytes as Id,
+/// and their Id commits to no root.
 #[serde_as]
 #[derive(Serialize, Deserialize, Clone, Derivative, Default)]
 #[derivative(Debug, PartialEq, Eq)]
@@ -29,9 +154,17 @@ pub struct Block {
     height: u64,
     /// Unix second when this block was proposed.
     timestamp: u64,
-    /// Arbitrary data.
+    /// Root of the Merkle tree over the entries, or over the single data as
+    /// an unsigned entry (see [`proposal_entries`](Block::proposal_entries)).
+    #[serde(default)]
+    merkle_root: ids::Id,
+    /// Arbitrary data of a single-proposal block.
+    /// Empty for blocks that carry `entries`.
     #[serde_as(as = "Hex0xBytes")]
     data: Vec<u8>,
+    /// Proposals packed into this block.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    entries: Vec<Entry>,
 
     /// Current block status.
     #[serde(skip)]
@@ -49,10 +182,24 @@ pub struct Block {
     state: state::State,
 }
 
+/// Represents the JSON encoding of blocks before the binary codec existed.
+/// Earlier versions read exactly these fields, in this order.
+#[serde_as]
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LegacyBlock {
+    parent_id: ids::Id,
+    height: u64,
+    timestamp: u64,
+    #[serde_as(as = "Hex0xBytes")]
+    data: Vec<u8>,
+}
+
 impl Block {
-    /// Can fail if the block can't be serialized to JSON.
+    /// Creates a single-proposal block with the JSON encoding of the earlier versions,
+    /// as built before the binary codec activates (and for the genesis block).
     /// # Errors
-    /// Will fail if the block can't be serialized to JSON.
+    /// Will fail if the block can't be encoded.
     pub fn try_new(
         parent_id: ids::Id,
         height: u64,
@@ -69,12 +216,45 @@ impl Block {
         };
 
         b.status = status;
-        b.bytes = b.to_vec()?;
+        b.merkle_root = b.compute_merkle_root()?;
+        b.bytes = b.to_legacy_json_vec()?;
         b.id = ids::Id::sha256(&b.bytes);
 
         Ok(b)
     }
 
+    /// Creates a block that carries multiple proposals, with the binary codec.
+    /// # Errors
+    /// Will fail if there are no entries or if the block can't be encoded.
+    pub fn try_new_with_entries(
+        parent_id: ids::Id,
+        height: u64,
+        timestamp: u64,
+        entries: Vec<Entry>,
+        status: choices::status::Status,
+    ) -> io::Result<Self> {
+        if entries.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "binary-encoded blocks must carry entries",
+            ));
+        }
+        let mut b = Self {
+            parent_id,
+            height,
+            timestamp,
+            entries,
+            ..Default::default()
+        };
+
+        b.status = status;
+        b.merkle_root = b.compute_merkle_root()?;
+        b.bytes = b.to_vec()?;
+        b.id = Self::compute_id(b.parent_id, b.height, b.timestamp, b.merkle_root)?;
+
+        Ok(b)
+    }
+
     /// # Errors
     /// Can fail if the block can't be serialized to JSON.
     pub fn to_json_string(&self) -> io::Result<String> {
@@ -86,11 +266,23 @@ impl Block {
         })
     }
 
-    /// Encodes the [`Block`](Block) to JSON in bytes.
+    /// Encodes the single-proposal [`Block`](Block) as JSON, like the earlier versions did.
     /// # Errors
-    /// Errors if the block can't be serialized to JSON.
-    pub fn to_vec(&self) -> io::Result<Vec<u8>> {
-        serde_json::to_vec(&self).map_err(|e| {
+    /// Errors if the block carries entries, which the earlier versions can't read.
+    pub fn to_legacy_json_vec(&self) -> io::Result<Vec<u8>> {
+        if !self.entries.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "JSON-encoded blocks can't carry entries",
+            ));
+        }
+        serde_json::to_vec(&LegacyBlock {
+            parent_id: self.parent_id,
+            height: self.height,
+            timestamp: self.timestamp,
+            data: self.data.clone(),
+        })
+        .map_err(|e| {
             Error::new(
                 ErrorKind::Other,
                 format!("failed to serialize Block to JSON bytes {e}"),
@@ -98,24 +290,177 @@ impl Block {
         })
     }
 
-    /// Loads [`Block`](Block) from JSON bytes.
+    /// Encodes the [`Block`](Block) with the binary [`codec`](crate::codec).
     /// # Errors
-    /// Will fail if the block can't be deserialized from JSON.
+    /// Errors if the block is too large to encode.
+    pub fn to_vec(&self) -> io::Result<Vec<u8>> {
+        let packer = codec::new_packer(
+            ENCODED_HEADER_LEN
+                + self.data.len()
+                + self.entries.iter().map(Entry::encoded_len).sum::<usize>(),
+        )?;
+        packer
+            .pack_bytes(self.parent_id.as_ref())
+            .map_err(codec::to_io_error)?;
+        packer.pack_u64(self.height).map_err(codec::to_io_error)?;
+        packer
+            .pack_u64(self.timestamp)
+            .map_err(codec::to_io_error)?;
+        packer
+            .pack_bytes(self.merkle_root.as_ref())
+            .map_err(codec::to_io_error)?;
+        packer
+            .pack_bytes_with_header(&self.data)
+            .map_err(codec::to_io_error)?;
+        packer
+            .pack_u32(u32::try_from(self.entries.len()).map_err(|e| {
+                Error::new(ErrorKind::InvalidInput, format!("too many entries {e}"))
+            })?)
+            .map_err(codec::to_io_error)?;
+        for entry in &self.entries {
+            entry.pack(&packer)?;
+        }
+        Ok(packer.take_bytes().to_vec())
+    }
+
+    /// Returns the Id of a block with the given header, the sha256 of its
+    /// encoding up to the Merkle root.
+    /// # Errors
+    /// Fails if the header can't be encoded.
+    pub fn compute_id(
+        parent_id: ids::Id,
+        height: u64,
+        timestamp: u64,
+        merkle_root: ids::Id,
+    ) -> io::Result<ids::Id> {
+        let packer = codec::new_packer(ID_PREIMAGE_LEN)?;
+        packer
+            .pack_bytes(parent_id.as_ref())
+            .map_err(codec::to_io_error)?;
+        packer.pack_u64(height).map_err(codec::to_io_error)?;
+        packer.pack_u64(timestamp).map_err(codec::to_io_error)?;
+        packer
+            .pack_bytes(merkle_root.as_ref())
+            .map_err(codec::to_io_error)?;
+        Ok(ids::Id::sha256(packer.take_bytes()))
+    }
+
+    /// Loads [`Block`](Block) from its binary encoding, or from the JSON
+    /// encoding of blocks created before the binary codec existed.
+    /// # Errors
+    /// Will fail if the block can't be decoded, or if its data or entries
+    /// don't match its Merkle root.
     pub fn from_slice(d: impl AsRef<[u8]>) -> io::Result<Self> {
         let dd = d.as_ref();
-        let mut b: Self = serde_json::from_slice(dd).map_err(|e| {
-            Error::new(
-                ErrorKind::Other,
-                format!("failed to deserialize Block from JSON {e}"),
-            )
-        })?;
+        let mut b = if codec::is_legacy_json(dd) {
+            let legacy: LegacyBlock = serde_json::from_slice(dd).map_err(|e| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("failed to deserialize Block from JSON {e}"),
+                )
+            })?;
+            let mut b = Self {
+                parent_id: legacy.parent_id,
+                height: legacy.height,
+                timestamp: legacy.timestamp,
+                data: legacy.data,
+                ..Default::default()
+            };
+            b.merkle_root = b.compute_merkle_root()?;
+            b.id = ids::Id::sha256(dd);
+            b
+        } else {
+            let mut b = Self::unpack(dd)?;
+            b.id = Self::compute_id(b.parent_id, b.height, b.timestamp, b.merkle_root)?;
+            b
+        };
 
         b.bytes = dd.to_vec();
-        b.id = ids::Id::sha256(&b.bytes);
 
         Ok(b)
     }
 
+    fn unpack(d: &[u8]) -> io::Result<Self> {
+        let packer = codec::load_packer(d)?;
+
+        let parent_id = packer.unpack_bytes(ids::LEN).map_err(codec::to_io_error)?;
+        let height = packer.unpack_u64().map_err(codec::to_io_error)?;
+        let timestamp = packer.unpack_u64().map_err(codec::to_io_error)?;
+        let merkle_root = packer.unpack_bytes(ids::LEN).map_err(codec::to_io_error)?;
+        let data = packer
+            .unpack_bytes_with_header()
+            .map_err(codec::to_io_error)?;
+        if !data.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "binary-encoded blocks carry entries instead of single data",
+            ));
+        }
+
+        // no pre-allocation, the count is not trusted until every entry is read
+        let num_entries = packer.unpack_u32().map_err(codec::to_io_error)?;
+        let mut entries = Vec::new();
+        for _ in 0..num_entries {
+            let data = packer
+                .unpack_bytes_with_header()
+                .map_err(codec::to_io_error)?;
+            let signature = ProposalSignature::unpack(&packer)?;
+            entries.push(Entry { data, signature });
+        }
+        codec::ensure_consumed(&packer)?;
+        if entries.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "binary-encoded blocks must carry entries",
+            ));
+        }
+
+        let b = Self {
+            parent_id: ids::Id::from_slice(&parent_id),
+            height,
+            timestamp,
+            merkle_root: ids::Id::from_slice(&merkle_root),
+            data,
+            entries,
+            ..Default::default()
+        };
+
+        // the Id only covers the root, so the contents must match it
+        let computed = b.compute_merkle_root()?;
+        if computed != b.merkle_root {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "merkle root {} does not match the block contents {computed}",
+                    b.merkle_root
+                ),
+            ));
+        }
+        Ok(b)
+    }
+
+    fn compute_merkle_root(&self) -> io::Result<ids::Id> {
+        Ok(merkle::root(&self.leaves()?))
+    }
+
+    /// Returns the Merkle leaves of this block, one per proposal.
+    /// # Errors
+    /// Fails if an entry can't be encoded.
+    pub fn leaves(&self) -> io::Result<Vec<ids::Id>> {
+        self.proposal_entries()
+            .iter()
+            .map(Entry::leaf_hash)
+            .collect()
+    }
+
+    /// Returns the Merkle inclusion path of the proposal at `index`,
+    /// or "None" if the index is out of range.
+    /// # Errors
+    /// Fails if an entry can't be encoded.
+    pub fn inclusion_proof(&self, index: usize) -> io::Result<Option<Vec<ids::Id>>> {
+        Ok(merkle::proof(&self.leaves()?, index))
+    }
+
     /// Returns the parent block Id.
     #[must_use]
     pub fn parent_id(&self) -> ids::Id {
@@ -134,12 +479,52 @@ impl Block {
         self.timestamp
     }
 
-    /// Returns the data of this block.
+    /// Returns the data of a single-proposal block.
+    /// Empty for blocks that carry entries, see [`payloads`](Block::payloads).
     #[must_use]
     pub fn data(&self) -> &[u8] {
         &self.data
     }
 
+    /// Returns the entries of this block.
+    #[must_use]
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// Returns the data of every proposal in this block,
+    /// regardless of whether it was encoded as entries or as single data.
+    #[must_use]
+    pub fn payloads(&self) -> Vec<&[u8]> {
+        if self.entries.is_empty() {
+            return vec![self.data.as_slice()];
+        }
+        self.entries.iter().map(Entry::data).collect()
+    }
+
+    /// Returns every proposal in this block as an entry,
+    /// where the single data of a single-proposal block is an unsigned entry.
+    #[must_use]
+    pub fn proposal_entries(&self) -> Vec<Entry> {
+        if self.entries.is_empty() {
+            return vec![Entry::new(self.data.clone())];
+        }
+        self.entries.clone()
+    }
+
+    /// Returns the Merkle root this block commits to.
+    #[must_use]
+    pub fn merkle_root(&self) -> ids::Id {
+        self.merkle_root
+    }
+
+    /// Returns "true" if this block was encoded as JSON before the binary codec
+    /// existed, and thus its Id does not cover its Merkle root.
+    #[must_use]
+    pub fn is_legacy_json(&self) -> bool {
+        codec::is_legacy_json(&self.bytes)
+    }
+
     /// Returns the status of this block.
     #[must_use]
     pub fn status(&self) -> choices::status::Status {
@@ -172,13 +557,15 @@ impl Block {
     /// and once verified, records it to the [`State`](crate::state::State).
     /// # Errors
     /// Can fail if the parent block can't be retrieved.
+    /// # Panics
+    /// Panics if the local time can't be converted to a unix second.
     pub async fn verify(&mut self) -> io::Result<()> {
         if self.height == 0 && self.parent_id == ids::Id::empty() {
             log::debug!(
                 "block {} has an empty parent Id since it's a genesis block -- skipping verify",
                 self.id
             );
-            self.state.add_verified(&self.clone()).await;
+            self.state.add_verified(&self.clone()).await?;
             return Ok(());
         }
 
@@ -191,6 +578,43 @@ impl Block {
 
         let prnt_blk = self.state.get_block(&self.parent_id).await?;
 
+        // rules are picked by the parent timestamp, known before this block is built
+        let rules = self.state.schedule.rules_at(prnt_blk.timestamp);
+
+        self.check_encoding(&rules)?;
+
+        // ensure the block fits the size limit
+        let block_size_limit = rules.block_size_limit;
+        if self.bytes.len() > block_size_limit {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "block size {} exceeds the limit {block_size_limit}",
+                    self.bytes.len()
+                ),
+            ));
+        }
+
+        // ensure each entry respects the proposal limit
+        let propose_limit_bytes = rules.propose_limit_bytes;
+        for entry in &self.entries {
+            if entry.data.len() > propose_limit_bytes {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "entry data {}-byte exceeds the limit {propose_limit_bytes}-byte",
+                        entry.data.len(),
+                    ),
+                ));
+            }
+            entry.verify_signature(&self.state.chain_id)?;
+        }
+
+        // the single data counts as an unsigned proposal, even if empty
+        for entry in &self.proposal_entries() {
+            rules.check_proposer(entry)?;
+        }
+
         // ensure the height of the block is immediately following its parent
         if prnt_blk.height != self.height - 1 {
             return Err(Error::new(
@@ -213,28 +637,44 @@ impl Block {
             ));
         }
 
-        let one_hour_from_now = Utc::now() + Duration::hours(1);
-        let one_hour_from_now = one_hour_from_now
+        let max_future_block_time_secs = rules.max_future_block_time_secs;
+        let unix_now: u64 = Utc::now()
             .timestamp()
             .try_into()
             .expect("failed to convert timestamp from i64 to u64");
 
-        // ensure block timestamp is no more than an hour ahead of this nodes time
-        if self.timestamp >= one_hour_from_now {
+        // ensure block timestamp is not too far ahead of this nodes time
+        if self.timestamp >= unix_now.saturating_add(max_future_block_time_secs) {
             return Err(Error::new(
                 ErrorKind::InvalidData,
                 format!(
-                    "block timestamp {} is more than 1 hour ahead of local time",
+                    "block timestamp {} is more than {max_future_block_time_secs} second(s) ahead of local time",
                     self.timestamp
                 ),
             ));
         }
 
         // add newly verified block to memory
-        self.state.add_verified(&self.clone()).await;
+        self.state.add_verified(&self.clone()).await?;
         Ok(())
     }
 
+    /// Checks that the block is encoded as the rules require, since every node
+    /// must build and accept the same encoding at the same block.
+    fn check_encoding(&self, rules: &upgrade::Rules) -> io::Result<()> {
+        match (rules.binary_blocks, self.is_legacy_json()) {
+            (true, true) => Err(Error::new(
+                ErrorKind::InvalidData,
+                "JSON-encoded blocks are no longer accepted",
+            )),
+            (false, false) => Err(Error::new(
+                ErrorKind::InvalidData,
+                "binary-encoded blocks are not accepted before the binary codec activates",
+            )),
+            _ => Ok(()),
+        }
+    }
+
     /// Mark this [`Block`](Block) accepted and updates [`State`](crate::state::State) accordingly.
     /// # Errors
     /// Returns an error if the state can't be updated.
@@ -242,10 +682,11 @@ impl Block {
         self.set_status(choices::status::Status::Accepted);
 
         // only decided blocks are persistent -- no reorg
-        self.state.write_block(&self.clone()).await?;
-        self.state.set_last_accepted_block(&self.id()).await?;
+        self.state.write_accepted_block(&self.clone()).await?;
 
-        self.state.remove_verified(&self.id()).await;
+        // conflicting blocks can't be accepted anymore, the engine rejects them later
+        self.state.prune_verified(&self.id()).await;
+        self.state.built_blocks.write().await.remove(&self.id());
         Ok(())
     }
 
@@ -255,10 +696,21 @@ impl Block {
     pub async fn reject(&mut self) -> io::Result<()> {
         self.set_status(choices::status::Status::Rejected);
 
-        // only decided blocks are persistent -- no reorg
-        self.state.write_block(&self.clone()).await?;
+        // rejected blocks are not persisted, so conflicting blocks can't grow the db
+        self.state.add_rejected(&self.clone()).await;
 
         self.state.remove_verified(&self.id()).await;
+
+        // the proposers were told their data was taken, so give it another chance,
+        // but only here, since blocks of other nodes may carry anything
+        Metrics::inc(&self.state.metrics.rejected_blocks);
+        if self.state.built_blocks.write().await.remove(&self.id()) {
+            self.state
+                .rejected_entries
+                .write()
+                .await
+                .extend(self.proposal_entries());
+        }
         Ok(())
     }
 }
@@ -270,8 +722,9 @@ impl fmt::Display for Block {
     }
 }
 
-/// RUST_LOG=debug cargo test --package timestampvm --lib -- block::test_block --exact --show-output
+/// `RUST_LOG=debug cargo test --package timestampvm --lib -- block::test_block --exact --show-output`
 #[tokio::test]
+#[allow(clippy::too_many_lines)]
 async fn test_block() {
     let _ = env_logger::builder()
         .filter_level(log::LevelFilter::Info)
@@ -281,15 +734,15 @@ async fn test_block() {
     let mut genesis_blk = Block::try_new(
         ids::Id::empty(),
         0,
-        Utc::now().timestamp() as u64,
+        u64::try_from(Utc::now().timestamp()).unwrap(),
         random_manager::secure_bytes(10).unwrap(),
         choices::status::Status::default(),
     )
     .unwrap();
     log::info!("deserialized: {genesis_blk} (block Id: {})", genesis_blk.id);
 
-    let serialized = genesis_blk.to_vec().unwrap();
-    let deserialized = Block::from_slice(&serialized).unwrap();
+    assert!(genesis_blk.is_legacy_json());
+    let deserialized = Block::from_slice(genesis_blk.bytes()).unwrap();
     log::info!("deserialized: {deserialized}");
 
     assert_eq!(genesis_blk, deserialized);
@@ -312,6 +765,11 @@ async fn test_block() {
 
     let last_accepted_blk_id = state.get_last_accepted_block_id().await.unwrap();
     assert_eq!(last_accepted_blk_id, genesis_blk.id());
+    assert!(state.is_height_index_complete().await.unwrap());
+    assert_eq!(
+        state.get_block_id_at_height(0).await.unwrap(),
+        genesis_blk.id()
+    );
 
     let read_blk = state.get_block(&genesis_blk.id()).await.unwrap();
     assert_eq!(genesis_blk, read_blk);
@@ -336,6 +794,7 @@ async fn test_block() {
 
     let last_accepted_blk_id = state.get_last_accepted_block_id().await.unwrap();
     assert_eq!(last_accepted_blk_id, blk1.id());
+    assert_eq!(state.get_block_id_at_height(1).await.unwrap(), blk1.id());
 
     let read_blk = state.get_block(&blk1.id()).await.unwrap();
     assert_eq!(blk1, read_blk);
@@ -361,6 +820,7 @@ async fn test_block() {
     // "blk2" is rejected, so last accepted block must be "blk1"
     let last_accepted_blk_id = state.get_last_accepted_block_id().await.unwrap();
     assert_eq!(last_accepted_blk_id, blk1.id());
+    assert!(state.get_block_id_at_height(2).await.is_err());
 
     let read_blk = state.get_block(&blk2.id()).await.unwrap();
     assert_eq!(blk2, read_blk);
@@ -384,7 +844,7 @@ async fn test_block() {
     let mut blk4 = Block::try_new(
         blk2.id,
         blk2.height + 1,
-        (Utc::now() + Duration::hours(2)).timestamp() as u64,
+        u64::try_from((Utc::now() + chrono::Duration::hours(2)).timestamp()).unwrap(),
         random_manager::secure_bytes(10).unwrap(),
         choices::status::Status::default(),
     )
@@ -396,12 +856,468 @@ async fn test_block() {
         .await
         .unwrap_err()
         .to_string()
-        .contains("1 hour ahead"));
+        .contains("3600 second(s) ahead"));
+}
+
+/// `RUST_LOG=debug cargo test --package timestampvm --lib -- block::test_block_entries --exact --show-output`
+#[tokio::test]
+#[allow(clippy::too_many_lines)]
+async fn test_block_entries() {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    // blocks encoded as JSON before the binary codec keep their bytes and Id
+    let legacy = br#"{"parent_id":"11111111111111111111111111111111LpoYY","height":0,"timestamp":0,"data":"0x0102"}"#;
+    let genesis_blk = Block::from_slice(legacy).unwrap();
+    assert_eq!(genesis_blk.id(), ids::Id::sha256(legacy));
+    assert_eq!(genesis_blk.bytes(), legacy.as_slice());
+    assert!(genesis_blk.entries().is_empty());
+    assert_eq!(genesis_blk.payloads(), vec![[1_u8, 2].as_slice()]);
+
+    assert_eq!(genesis_blk.to_legacy_json_vec().unwrap(), legacy.to_vec());
+    let rebuilt = Block::try_new(
+        genesis_blk.parent_id(),
+        0,
+        0,
+        vec![1, 2],
+        choices::status::Status::default(),
+    )
+    .unwrap();
+    assert_eq!(rebuilt.id(), genesis_blk.id());
+    assert!(Block::from_slice(br#"{"parent_id":"11111111111111111111111111111111LpoYY","height":0,"timestamp":0,"data":"0x0102","entries":[]}"#).is_err());
+
+    let mut state = state::State::default();
+    let mut genesis_blk = genesis_blk;
+    genesis_blk.set_state(state.clone());
+    genesis_blk.accept().await.unwrap();
+
+    // binary blocks are rejected until the rules turn them on
+    let mut binary_blk = Block::try_new_with_entries(
+        genesis_blk.id(),
+        1,
+        1,
+        vec![Entry::new(vec![1])],
+        choices::status::Status::default(),
+    )
+    .unwrap();
+    binary_blk.set_state(state.clone());
+    assert!(binary_blk
+        .verify()
+        .await
+        .unwrap_err()
+        .to_string()
+        .contains("before the binary codec activates"));
+    state.schedule.base.binary_blocks = true;
+
+    let entries = vec![Entry::new(vec![1; 10]), Entry::new(vec![2; 20])];
+    let mut blk1 = Block::try_new_with_entries(
+        genesis_blk.id(),
+        1,
+        1,
+        entries.clone(),
+        choices::status::Status::default(),
+    )
+    .unwrap();
+    let decoded = Block::from_slice(blk1.bytes()).unwrap();
+    assert_eq!(decoded.entries(), entries.as_slice());
+    assert_eq!(
+        decoded.payloads(),
+        vec![[1; 10].as_slice(), [2; 20].as_slice()]
+    );
+
+    // size estimate used by the block builder is exact
+    assert!(Block::try_new_with_entries(
+        genesis_blk.id(),
+        1,
+        1,
+        Vec::new(),
+        choices::status::Status::default(),
+    )
+    .is_err());
+    let estimated = ENCODED_HEADER_LEN + entries.iter().map(Entry::encoded_len).sum::<usize>();
+    assert_eq!(estimated, blk1.bytes().len());
+
+    state.schedule.base.block_size_limit = blk1.bytes().len() - 1;
+    blk1.set_state(state.clone());
+    assert!(blk1
+        .verify()
+        .await
+        .unwrap_err()
+        .to_string()
+        .contains("exceeds the limit"));
+
+    state.schedule.base.block_size_limit = blk1.bytes().len();
+    blk1.set_state(state.clone());
+    blk1.verify().await.unwrap();
+    state.remove_verified(&blk1.id()).await;
+
+    // signed entries record their proposer, and signatures are checked again in verify
+    state.schedule.base.block_size_limit = DEFAULT_BLOCK_SIZE_LIMIT;
+    let key = avalanche_types::key::secp256k1::private_key::Key::generate().unwrap();
+    let sig =
+        signature::ProposalSignature::sign_secp256k1(&key, &state.chain_id, &[3; 10]).unwrap();
+    let proposer = key.to_public_key().to_short_id().unwrap();
+    assert_eq!(sig.proposer, proposer);
+    let signed = vec![
+        Entry::new_signed(vec![3; 10], sig.clone()),
+        Entry::new(vec![4; 10]),
+    ];
+    let mut signed_blk = Block::try_new_with_entries(
+        genesis_blk.id(),
+        1,
+        1,
+        signed.clone(),
+        choices::status::Status::default(),
+    )
+    .unwrap();
+    let estimated = ENCODED_HEADER_LEN + signed.iter().map(Entry::encoded_len).sum::<usize>();
+    assert_eq!(estimated, signed_blk.bytes().len());
+    let decoded = Block::from_slice(signed_blk.bytes()).unwrap();
+    assert_eq!(decoded.entries(), signed.as_slice());
+    assert_eq!(decoded.entries()[0].proposer(), Some(proposer.clone()));
+    assert_eq!(decoded.entries()[1].proposer(), None);
+    assert!(signed_blk
+        .to_json_string()
+        .unwrap()
+        .contains(&format!("\"proposer\":\"{proposer}\"")));
+    signed_blk.set_state(state.clone());
+    signed_blk.verify().await.unwrap();
+    state.remove_verified(&signed_blk.id()).await;
+
+    let mut forged_blk = Block::try_new_with_entries(
+        genesis_blk.id(),
+        1,
+        1,
+        vec![Entry::new_signed(vec![4; 10], sig.clone())],
+        choices::status::Status::default(),
+    )
+    .unwrap();
+    forged_blk.set_state(state.clone());
+    assert!(forged_blk
+        .verify()
+        .await
+        .unwrap_err()
+        .to_string()
+        .contains("invalid proposal signature"));
+
+    // signatures are bound to the chain
+    let other_chain_sig = signature::ProposalSignature::sign_secp256k1(
+        &key,
+        &ids::Id::from_slice(&[1; 32]),
+        &[3; 10],
+    )
+    .unwrap();
+    let mut replayed_blk = Block::try_new_with_entries(
+        genesis_blk.id(),
+        1,
+        1,
+        vec![Entry::new_signed(vec![3; 10], other_chain_sig)],
+        choices::status::Status::default(),
+    )
+    .unwrap();
+    replayed_blk.set_state(state.clone());
+    assert!(replayed_blk
+        .verify()
+        .await
+        .unwrap_err()
+        .to_string()
+        .contains("invalid proposal signature"));
+
+    let mut wrong_proposer = sig;
+    wrong_proposer.proposer = ids::short::Id::from_slice(&[1; 20]);
+    let mut forged_blk = Block::try_new_with_entries(
+        genesis_blk.id(),
+        1,
+        1,
+        vec![Entry::new_signed(vec![3; 10], wrong_proposer)],
+        choices::status::Status::default(),
+    )
+    .unwrap();
+    forged_blk.set_state(state.clone());
+    assert!(forged_blk
+        .verify()
+        .await
+        .unwrap_err()
+        .to_string()
+        .contains("does not match the public key"));
+
+    // only allowed proposers can sign entries once the genesis or an upgrade restricts them
+    let other_key = avalanche_types::key::secp256k1::private_key::Key::generate().unwrap();
+    let other_sig =
+        signature::ProposalSignature::sign_secp256k1(&other_key, &state.chain_id, &[4; 10])
+            .unwrap();
+    state.schedule.base.allowed_proposers = vec![proposer];
+    for (entries, err) in [
+        (signed.clone(), "unsigned proposals are not allowed"),
+        (
+            vec![Entry::new_signed(vec![4; 10], other_sig.clone())],
+            "is not allowed",
+        ),
+    ] {
+        let mut blk = Block::try_new_with_entries(
+            genesis_blk.id(),
+            1,
+            1,
+            entries,
+            choices::status::Status::default(),
+        )
+        .unwrap();
+        blk.set_state(state.clone());
+        assert!(blk.verify().await.unwrap_err().to_string().contains(err));
+    }
+    state.schedule.base.binary_blocks = false;
+    let mut empty_blk = Block::try_new(
+        genesis_blk.id(),
+        1,
+        1,
+        Vec::new(),
+        choices::status::Status::default(),
+    )
+    .unwrap();
+    empty_blk.set_state(state.clone());
+    assert!(empty_blk
+        .verify()
+        .await
+        .unwrap_err()
+        .to_string()
+        .contains("unsigned proposals are not allowed"));
+    state.schedule.base.binary_blocks = true;
+    let mut allowed_blk = Block::try_new_with_entries(
+        genesis_blk.id(),
+        1,
+        1,
+        signed[..1].to_vec(),
+        choices::status::Status::default(),
+    )
+    .unwrap();
+    allowed_blk.set_state(state.clone());
+    allowed_blk.verify().await.unwrap();
+    state.remove_verified(&allowed_blk.id()).await;
+    state.schedule.base.allowed_proposers.clear();
+
+    // rules of an upgrade apply once the parent timestamp reaches its activation
+    state.schedule = crate::upgrade::Schedule::try_new(
+        crate::upgrade::Rules::default(),
+        vec![crate::upgrade::Upgrade {
+            name: String::from("small"),
+            activation_timestamp: genesis_blk.timestamp(),
+            rules: crate::upgrade::RuleOverrides {
+                propose_limit_bytes: Some(15),
+                binary_blocks: Some(true),
+                ..Default::default()
+            },
+        }],
+    )
+    .unwrap();
+    blk1.set_state(state.clone());
+    assert!(blk1
+        .verify()
+        .await
+        .unwrap_err()
+        .to_string()
+        .contains("entry data 20-byte exceeds the limit 15-byte"));
+
+    let mut json_blk = Block::try_new(
+        genesis_blk.id(),
+        1,
+        1,
+        vec![1],
+        choices::status::Status::default(),
+    )
+    .unwrap();
+    json_blk.set_state(state.clone());
+    assert!(json_blk
+        .verify()
+        .await
+        .unwrap_err()
+        .to_string()
+        .contains("no longer accepted"));
+}
+
+/// `RUST_LOG=debug cargo test --package timestampvm --lib -- block::test_reject_pruned --exact --show-output`
+#[tokio::test]
+async fn test_reject_pruned() {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let mut state = state::State::default();
+    state.schedule.base.binary_blocks = true;
+    let mut genesis_blk = Block::try_new(
+        ids::Id::empty(),
+        0,
+        0,
+        random_manager::secure_bytes(10).unwrap(),
+        choices::status::Status::default(),
+    )
+    .unwrap();
+    genesis_blk.set_state(state.clone());
+    genesis_blk.accept().await.unwrap();
+
+    // two siblings, the second one built by this node
+    let mut siblings = Vec::new();
+    for d in [vec![1; 10], vec![2; 10]] {
+        let mut blk = Block::try_new_with_entries(
+            genesis_blk.id(),
+            1,
+            0,
+            vec![Entry::new(d)],
+            choices::status::Status::Processing,
+        )
+        .unwrap();
+        blk.set_state(state.clone());
+        blk.verify().await.unwrap();
+        siblings.push(blk);
+    }
+    let (mut accepted, conflicting) = (siblings[0].clone(), siblings[1].clone());
+    state.built_blocks.write().await.insert(conflicting.id());
+
+    accepted.accept().await.unwrap();
+    assert!(state.verified_blocks.read().await.is_empty());
+
+    // the engine looks the conflicting block up before rejecting it
+    let mut blk = state.get_block(&conflicting.id()).await.unwrap();
+    assert_eq!(blk.status(), choices::status::Status::Processing);
+    blk.reject().await.unwrap();
+    assert_eq!(
+        state.get_block(&conflicting.id()).await.unwrap().status(),
+        choices::status::Status::Rejected
+    );
+    assert!(state.pruned_blocks.read().await.is_empty());
+    assert!(state.built_blocks.read().await.is_empty());
+    assert_eq!(
+        *state.rejected_entries.read().await,
+        conflicting.proposal_entries()
+    );
+}
+
+/// `RUST_LOG=debug cargo test --package timestampvm --lib -- block::test_block_codec --exact --show-output`
+#[test]
+#[allow(clippy::too_many_lines)]
+fn test_block_codec() {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let blk = Block::try_new_with_entries(
+        ids::Id::from_slice(&[0xaa; 32]),
+        1,
+        2,
+        vec![Entry::new(vec![3, 4]), Entry::new(vec![5])],
+        choices::status::Status::default(),
+    )
+    .unwrap();
+
+    let merkle_root = merkle::root(&[
+        merkle::leaf_hash(&[0, 0, 0, 2, 3, 4, 0]),
+        merkle::leaf_hash(&[0, 0, 0, 1, 5, 0]),
+    ]);
+    assert_eq!(blk.merkle_root(), merkle_root);
+
+    let mut expected = vec![0x00, 0x00]; // codec version
+    expected.extend_from_slice(&[0xaa; 32]);
+    expected.extend_from_slice(&1_u64.to_be_bytes());
+    expected.extend_from_slice(&2_u64.to_be_bytes());
+    expected.extend_from_slice(merkle_root.as_ref());
+    expected.extend_from_slice(&[0, 0, 0, 0]); // empty single data
+    expected.extend_from_slice(&[0, 0, 0, 2]);
+    expected.extend_from_slice(&[0, 0, 0, 2, 3, 4, 0]); // unsigned
+    expected.extend_from_slice(&[0, 0, 0, 1, 5, 0]);
+    assert_eq!(blk.bytes(), expected.as_slice());
+    assert_eq!(blk.id(), ids::Id::sha256(&expected[..ID_PREIMAGE_LEN]));
+
+    let decoded = Block::from_slice(&expected).unwrap();
+    assert_eq!(decoded, blk);
+    assert_eq!(decoded.id(), blk.id());
+
+    // canonical: no trailing bytes, no truncation, known version only
+    let mut trailing = expected.clone();
+    trailing.push(0);
+    assert!(Block::from_slice(&trailing).is_err());
+    assert!(Block::from_slice(&expected[..expected.len() - 1]).is_err());
+    let mut unknown_scheme = expected.clone();
+    *unknown_scheme.last_mut().unwrap() = 0xff;
+    assert!(Block::from_slice(&unknown_scheme)
+        .unwrap_err()
+        .to_string()
+        .contains("unknown signature scheme"));
+    let mut unknown_version = expected.clone();
+    unknown_version[1] = 1;
+    assert!(Block::from_slice(&unknown_version)
+        .unwrap_err()
+        .to_string()
+        .contains("unsupported codec version"));
+
+    // contents must match the root the Id covers
+    let mut tampered = expected.clone();
+    let last = tampered.len() - 2;
+    tampered[last] = 6;
+    assert!(Block::from_slice(&tampered)
+        .unwrap_err()
+        .to_string()
+        .contains("does not match the block contents"));
+
+    // huge entry counts fail without allocating
+    let mut huge = expected[..expected.len() - 17].to_vec();
+    huge.extend_from_slice(&[0xff, 0xff, 0xff, 0xff]);
+    assert!(Block::from_slice(&huge).is_err());
+
+    // one encoding per block: the single data can't stand in for an entry,
+    // nor ride along with entries outside the root
+    let encode = |merkle_root: ids::Id, data: &[u8], entries: &[u8]| {
+        let mut d = expected[..ID_PREIMAGE_LEN - ids::LEN].to_vec();
+        d.extend_from_slice(merkle_root.as_ref());
+        d.extend_from_slice(&u32::try_from(data.len()).unwrap().to_be_bytes());
+        d.extend_from_slice(data);
+        d.extend_from_slice(entries);
+        d
+    };
+    let single = Block::try_new_with_entries(
+        ids::Id::from_slice(&[0xaa; 32]),
+        1,
+        2,
+        vec![Entry::new(vec![3, 4])],
+        choices::status::Status::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        Block::from_slice(encode(
+            single.merkle_root(),
+            &[],
+            &[0, 0, 0, 1, 0, 0, 0, 2, 3, 4, 0]
+        ))
+        .unwrap(),
+        single
+    );
+    assert!(
+        Block::from_slice(encode(single.merkle_root(), &[3, 4], &[0, 0, 0, 0]))
+            .unwrap_err()
+            .to_string()
+            .contains("instead of single data")
+    );
+    assert!(Block::from_slice(encode(
+        merkle_root,
+        &[9],
+        &expected[ENCODED_HEADER_LEN - 4..]
+    ))
+    .unwrap_err()
+    .to_string()
+    .contains("instead of single data"));
+    assert!(
+        Block::from_slice(encode(merkle::root(&[]), &[], &[0, 0, 0, 0]))
+            .unwrap_err()
+            .to_string()
+            .contains("must carry entries")
+    );
 }
 
 #[tonic::async_trait]
 impl snowman::Block for Block {
-    async fn bytes(&self) -> &[u8] {
+    async fn bytes(&self) -> &'life0 [u8] {
         return self.bytes.as_ref();
     }
 
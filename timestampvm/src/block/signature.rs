@@ -0,0 +1,264 @@
+//! Signatures that bind a proposal to the key of its proposer.
+//!
+//! A proposer signs the sha256 digest of [`DOMAIN_TAG`](DOMAIN_TAG), the chain Id and
+//! the proposed data, so that a signature is only valid for proposals to that chain.
+//! The signature travels with the data through the mempool, gossip and the block,
+//! and every node checks it both when the proposal is submitted and when the block is verified.
+
+use std::io::{self, Error, ErrorKind};
+
+use avalanche_types::{
+    codec::serde::hex_0x_bytes::Hex0xBytes,
+    ids,
+    key::secp256k1::{self, private_key, public_key},
+    packer::{self, Packer},
+};
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+
+use crate::codec;
+
+/// Prefix of every signed digest, so that proposal signatures can't be
+/// mistaken for signatures over other messages of the same key.
+pub const DOMAIN_TAG: &[u8] = b"timestampvm proposal";
+
+/// Returns the digest a proposer signs to propose the data to the chain.
+#[must_use]
+pub fn signing_digest(chain_id: &ids::Id, data: &[u8]) -> ids::Id {
+    let mut msg = Vec::with_capacity(DOMAIN_TAG.len() + ids::LEN + data.len());
+    msg.extend_from_slice(DOMAIN_TAG);
+    msg.extend_from_slice(chain_id.as_ref());
+    msg.extend_from_slice(data);
+    ids::Id::sha256(msg)
+}
+
+/// Scheme byte of entries without a signature.
+pub const UNSIGNED_TYPE_ID: u8 = 0;
+
+/// Length of the scheme byte.
+pub const TYPE_ID_LEN: usize = packer::BYTE_LEN;
+
+/// Key scheme of a [`ProposalSignature`](ProposalSignature).
+///
+/// Only secp256k1 is supported, since `avalanche_types` has no ed25519 key module.
+/// The scheme is encoded as its own byte, so that other schemes can be added later.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Scheme {
+    /// Compressed 33-byte public key and 65-byte recoverable signature,
+    /// as used by the X/P-chain keys.
+    #[default]
+    Secp256k1,
+}
+
+impl Scheme {
+    /// Byte that identifies the scheme in the binary codec.
+    /// Zero is reserved for unsigned entries.
+    #[must_use]
+    pub fn type_id(self) -> u8 {
+        match self {
+            Scheme::Secp256k1 => 1,
+        }
+    }
+
+    /// Returns the scheme identified by the byte, if any.
+    #[must_use]
+    pub fn from_type_id(type_id: u8) -> Option<Self> {
+        match type_id {
+            1 => Some(Scheme::Secp256k1),
+            _ => None,
+        }
+    }
+
+    /// Returns the length of the public keys of this scheme.
+    #[must_use]
+    pub fn public_key_len(self) -> usize {
+        match self {
+            Scheme::Secp256k1 => public_key::LEN,
+        }
+    }
+
+    /// Returns the length of the signatures of this scheme.
+    #[must_use]
+    pub fn signature_len(self) -> usize {
+        match self {
+            Scheme::Secp256k1 => secp256k1::signature::LEN,
+        }
+    }
+}
+
+/// Signature of a proposer over the [`signing_digest`](signing_digest) of the proposed data.
+#[serde_as]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ProposalSignature {
+    pub scheme: Scheme,
+    #[serde_as(as = "Hex0xBytes")]
+    pub public_key: Vec<u8>,
+    #[serde_as(as = "Hex0xBytes")]
+    pub signature: Vec<u8>,
+    /// Address of the proposer, derived from the public key.
+    pub proposer: ids::short::Id,
+}
+
+impl ProposalSignature {
+    /// Creates the signature from the encoded key and signature,
+    /// and derives the proposer address.
+    /// The signature itself is only checked by [`verify`](ProposalSignature::verify).
+    /// # Errors
+    /// Fails if the public key is malformed.
+    pub fn try_new(scheme: Scheme, public_key: Vec<u8>, signature: Vec<u8>) -> io::Result<Self> {
+        let proposer = Self::address_of(scheme, &public_key)?;
+        Ok(Self {
+            scheme,
+            public_key,
+            signature,
+            proposer,
+        })
+    }
+
+    /// Signs the data proposed to the chain with the secp256k1 key.
+    /// # Errors
+    /// Fails if the data can't be signed.
+    pub fn sign_secp256k1(
+        key: &private_key::Key,
+        chain_id: &ids::Id,
+        data: &[u8],
+    ) -> io::Result<Self> {
+        let digest = signing_digest(chain_id, data);
+        let sig = key
+            .sign_digest(digest.as_ref())
+            .map_err(|e| Error::new(ErrorKind::Other, format!("failed to sign proposal {e}")))?;
+        Self::try_new(
+            Scheme::Secp256k1,
+            key.to_public_key().to_compressed_bytes().to_vec(),
+            sig.to_bytes().to_vec(),
+        )
+    }
+
+    /// Returns the address of the public key.
+    /// # Errors
+    /// Fails if the public key is malformed.
+    pub fn address_of(scheme: Scheme, public_key: &[u8]) -> io::Result<ids::short::Id> {
+        match scheme {
+            Scheme::Secp256k1 => {
+                if public_key.len() != public_key::LEN {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!(
+                            "public key is {}-byte, expected compressed {}-byte",
+                            public_key.len(),
+                            public_key::LEN
+                        ),
+                    ));
+                }
+                let key = public_key::Key::from_sec1_bytes(public_key).map_err(|e| {
+                    Error::new(ErrorKind::InvalidData, format!("invalid public key {e}"))
+                })?;
+                key.to_short_id().map_err(codec::to_io_error)
+            }
+        }
+    }
+
+    /// Checks that the signature is valid for the data proposed to the chain
+    /// and the proposer address matches the public key.
+    /// # Errors
+    /// Fails with `InvalidData` if any check fails.
+    pub fn verify(&self, chain_id: &ids::Id, data: &[u8]) -> io::Result<()> {
+        let proposer = Self::address_of(self.scheme, &self.public_key)?;
+        if proposer != self.proposer {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "proposer {} does not match the public key address {proposer}",
+                    self.proposer
+                ),
+            ));
+        }
+
+        let digest = signing_digest(chain_id, data);
+        let valid = match self.scheme {
+            Scheme::Secp256k1 => public_key::Key::from_sec1_bytes(&self.public_key)
+                .and_then(|key| key.verify(digest.as_ref(), &self.signature))
+                .unwrap_or(false),
+        };
+        if !valid {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("invalid proposal signature from {proposer}"),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the number of bytes this signature adds to an encoded entry,
+    /// in addition to the scheme byte.
+    #[must_use]
+    pub fn encoded_len(&self) -> usize {
+        self.public_key.len() + self.signature.len() + ids::short::LEN
+    }
+
+    /// Packs the signature, including its scheme byte.
+    /// # Errors
+    /// Fails if the key or signature length does not match the scheme.
+    pub fn pack(&self, packer: &Packer) -> io::Result<()> {
+        if self.public_key.len() != self.scheme.public_key_len()
+            || self.signature.len() != self.scheme.signature_len()
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "{}-byte public key and {}-byte signature don't match the scheme {:?}",
+                    self.public_key.len(),
+                    self.signature.len(),
+                    self.scheme
+                ),
+            ));
+        }
+        packer
+            .pack_byte(self.scheme.type_id())
+            .map_err(codec::to_io_error)?;
+        packer
+            .pack_bytes(&self.public_key)
+            .map_err(codec::to_io_error)?;
+        packer
+            .pack_bytes(&self.signature)
+            .map_err(codec::to_io_error)?;
+        packer
+            .pack_bytes(self.proposer.as_ref())
+            .map_err(codec::to_io_error)?;
+        Ok(())
+    }
+
+    /// Unpacks an optional signature, starting with its scheme byte
+    /// where zero means unsigned.
+    /// # Errors
+    /// Fails if the scheme is unknown or the bytes are truncated.
+    pub fn unpack(packer: &Packer) -> io::Result<Option<Self>> {
+        let type_id = packer.unpack_byte().map_err(codec::to_io_error)?;
+        if type_id == UNSIGNED_TYPE_ID {
+            return Ok(None);
+        }
+        let scheme = Scheme::from_type_id(type_id).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("unknown signature scheme {type_id}"),
+            )
+        })?;
+
+        let public_key = packer
+            .unpack_bytes(scheme.public_key_len())
+            .map_err(codec::to_io_error)?;
+        let signature = packer
+            .unpack_bytes(scheme.signature_len())
+            .map_err(codec::to_io_error)?;
+        let proposer = packer
+            .unpack_bytes(ids::short::LEN)
+            .map_err(codec::to_io_error)?;
+        Ok(Some(Self {
+            scheme,
+            public_key,
+            signature,
+            proposer: ids::short::Id::from_slice(&proposer),
+        }))
+    }
+}
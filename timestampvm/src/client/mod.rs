@@ -1,13 +1,22 @@
 //! Implements client for timestampvm APIs.
 
+pub mod timestampvm_client;
+
 use std::{
     collections::HashMap,
     io::{self, Error, ErrorKind},
 };
 
+use crate::{
+    api::chain_handlers::ProposeBlockArgs,
+    block::{signature::ProposalSignature, Block, Entry},
+    merkle,
+};
 use avalanche_types::{ids, jsonrpc};
 use serde::{Deserialize, Serialize};
 
+pub use timestampvm_client::{ClientError, RetryPolicy, TimestampVmClient};
+
 /// Represents the RPC response for API `ping`.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PingResponse {
@@ -28,8 +37,10 @@ pub struct PingResponse {
 pub async fn ping(http_rpc: &str, url_path: &str) -> io::Result<PingResponse> {
     log::info!("ping {http_rpc} with {url_path}");
 
-    let mut data = jsonrpc::RequestWithParamsArray::default();
-    data.method = String::from("timestampvm.ping");
+    let data = jsonrpc::RequestWithParamsArray {
+        method: String::from("timestampvm.ping"),
+        ..Default::default()
+    };
 
     let d = data.encode_json()?;
     let rb = http_manager::post_non_tls(http_rpc, url_path, &d).await?;
@@ -58,8 +69,10 @@ pub struct LastAcceptedResponse {
 pub async fn last_accepted(http_rpc: &str, url_path: &str) -> io::Result<LastAcceptedResponse> {
     log::info!("last_accepted {http_rpc} with {url_path}");
 
-    let mut data = jsonrpc::RequestWithParamsArray::default();
-    data.method = String::from("timestampvm.lastAccepted");
+    let data = jsonrpc::RequestWithParamsArray {
+        method: String::from("timestampvm.lastAccepted"),
+        ..Default::default()
+    };
 
     let d = data.encode_json()?;
     let rb = http_manager::post_non_tls(http_rpc, url_path, &d).await?;
@@ -92,14 +105,14 @@ pub async fn get_block(
 ) -> io::Result<GetBlockResponse> {
     log::info!("get_block {http_rpc} with {url_path}");
 
-    let mut data = jsonrpc::RequestWithParamsHashMapArray::default();
-    data.method = String::from("timestampvm.getBlock");
-
     let mut m = HashMap::new();
     m.insert("id".to_string(), id.to_string());
 
-    let params = vec![m];
-    data.params = Some(params);
+    let data = jsonrpc::RequestWithParamsHashMapArray {
+        method: String::from("timestampvm.getBlock"),
+        params: Some(vec![m]),
+        ..Default::default()
+    };
 
     let d = data.encode_json()?;
     let rb = http_manager::post_non_tls(http_rpc, url_path, &d).await?;
@@ -108,6 +121,95 @@ pub async fn get_block(
         .map_err(|e| Error::new(ErrorKind::Other, format!("failed get_block '{e}'")))
 }
 
+/// Represents the RPC response for API `get_block_by_height`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GetBlockByHeightResponse {
+    pub jsonrpc: String,
+    pub id: u32,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<crate::api::chain_handlers::BlockWithId>,
+
+    /// Returns non-empty if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<APIError>,
+}
+
+/// Fetches the accepted block at the height (if any).
+/// # Errors
+/// Errors on failed (de)serialization or an http failure.
+pub async fn get_block_by_height(
+    http_rpc: &str,
+    url_path: &str,
+    height: u64,
+) -> io::Result<GetBlockByHeightResponse> {
+    log::info!("get_block_by_height {http_rpc} with {url_path}");
+
+    let mut m = HashMap::new();
+    m.insert("height".to_string(), height.to_string());
+
+    let data = jsonrpc::RequestWithParamsHashMapArray {
+        method: String::from("timestampvm.getBlockByHeight"),
+        params: Some(vec![m]),
+        ..Default::default()
+    };
+
+    let d = data.encode_json()?;
+    let rb = http_manager::post_non_tls(http_rpc, url_path, &d).await?;
+
+    serde_json::from_slice(&rb).map_err(|e| {
+        Error::new(
+            ErrorKind::Other,
+            format!("failed get_block_by_height '{e}'"),
+        )
+    })
+}
+
+/// Represents the RPC response for API `get_blocks`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GetBlocksResponse {
+    pub jsonrpc: String,
+    pub id: u32,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<crate::api::chain_handlers::GetBlocksResponse>,
+
+    /// Returns non-empty if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<APIError>,
+}
+
+/// Fetches a page of accepted blocks from `start_height` in ascending height order.
+/// The node caps `limit`, so follow `next_height` for further pages.
+/// # Errors
+/// Errors on failed (de)serialization or an http failure.
+pub async fn get_blocks(
+    http_rpc: &str,
+    url_path: &str,
+    start_height: u64,
+    limit: Option<u32>,
+) -> io::Result<GetBlocksResponse> {
+    log::info!("get_blocks {http_rpc} with {url_path}");
+
+    let mut m = HashMap::new();
+    m.insert("startHeight".to_string(), start_height.to_string());
+    if let Some(limit) = limit {
+        m.insert("limit".to_string(), limit.to_string());
+    }
+
+    let data = jsonrpc::RequestWithParamsHashMapArray {
+        method: String::from("timestampvm.getBlocks"),
+        params: Some(vec![m]),
+        ..Default::default()
+    };
+
+    let d = data.encode_json()?;
+    let rb = http_manager::post_non_tls(http_rpc, url_path, &d).await?;
+
+    serde_json::from_slice(&rb)
+        .map_err(|e| Error::new(ErrorKind::Other, format!("failed get_blocks '{e}'")))
+}
+
 /// Represents the RPC response for API `propose_block`.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ProposeBlockResponse {
@@ -132,18 +234,66 @@ pub async fn propose_block(
     d: Vec<u8>,
 ) -> io::Result<ProposeBlockResponse> {
     log::info!("propose_block {http_rpc} with {url_path}");
+    send_propose_block(
+        http_rpc,
+        url_path,
+        ProposeBlockArgs {
+            data: d,
+            scheme: None,
+            public_key: None,
+            signature: None,
+        },
+    )
+    .await
+}
 
-    let mut data = jsonrpc::RequestWithParamsHashMapArray::default();
-    data.method = String::from("timestampvm.proposeBlock");
-
-    let mut m = HashMap::new();
-    m.insert(
-        "data".to_string(),
-        base64::Engine::encode(&base64::engine::general_purpose::STANDARD, &d),
+/// Proposes arbitrary data signed by the proposer,
+/// e.g., with [`ProposalSignature::sign_secp256k1`](ProposalSignature::sign_secp256k1).
+/// # Errors
+/// Errors on failed (de)serialization or an http failure.
+pub async fn propose_signed_block(
+    http_rpc: &str,
+    url_path: &str,
+    d: Vec<u8>,
+    signature: &ProposalSignature,
+) -> io::Result<ProposeBlockResponse> {
+    log::info!(
+        "propose_signed_block {http_rpc} with {url_path} by {}",
+        signature.proposer
     );
+    send_propose_block(
+        http_rpc,
+        url_path,
+        ProposeBlockArgs {
+            data: d,
+            scheme: Some(signature.scheme),
+            public_key: Some(signature.public_key.clone()),
+            signature: Some(signature.signature.clone()),
+        },
+    )
+    .await
+}
 
-    let params = vec![m];
-    data.params = Some(params);
+async fn send_propose_block(
+    http_rpc: &str,
+    url_path: &str,
+    args: ProposeBlockArgs,
+) -> io::Result<ProposeBlockResponse> {
+    // every argument is encoded as a JSON string
+    let m: HashMap<String, String> = serde_json::to_value(&args)
+        .and_then(serde_json::from_value)
+        .map_err(|e| {
+            Error::new(
+                ErrorKind::Other,
+                format!("failed to encode propose_block args '{e}'"),
+            )
+        })?;
+
+    let data = jsonrpc::RequestWithParamsHashMapArray {
+        method: String::from("timestampvm.proposeBlock"),
+        params: Some(vec![m]),
+        ..Default::default()
+    };
 
     let d = data.encode_json()?;
     let rb = http_manager::post_non_tls(http_rpc, url_path, &d).await?;
@@ -152,6 +302,230 @@ pub async fn propose_block(
         .map_err(|e| Error::new(ErrorKind::Other, format!("failed propose_block '{e}'")))
 }
 
+/// Represents the RPC response for API `get_proposal_status`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GetProposalStatusResponse {
+    pub jsonrpc: String,
+    pub id: u32,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<crate::api::chain_handlers::GetProposalStatusResponse>,
+
+    /// Returns non-empty if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<APIError>,
+}
+
+/// Fetches the status of a proposal, by the Id returned from [`propose_block`](propose_block).
+/// # Errors
+/// Errors on failed (de)serialization or an http failure.
+pub async fn get_proposal_status(
+    http_rpc: &str,
+    url_path: &str,
+    id: &ids::Id,
+) -> io::Result<GetProposalStatusResponse> {
+    log::info!("get_proposal_status {http_rpc} with {url_path}");
+
+    let mut m = HashMap::new();
+    m.insert("id".to_string(), id.to_string());
+
+    let data = jsonrpc::RequestWithParamsHashMapArray {
+        method: String::from("timestampvm.getProposalStatus"),
+        params: Some(vec![m]),
+        ..Default::default()
+    };
+
+    let d = data.encode_json()?;
+    let rb = http_manager::post_non_tls(http_rpc, url_path, &d).await?;
+
+    serde_json::from_slice(&rb).map_err(|e| {
+        Error::new(
+            ErrorKind::Other,
+            format!("failed get_proposal_status '{e}'"),
+        )
+    })
+}
+
+/// Represents the RPC response for API `get_inclusion_proof`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GetInclusionProofResponse {
+    pub jsonrpc: String,
+    pub id: u32,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<crate::api::chain_handlers::GetInclusionProofResponse>,
+
+    /// Returns non-empty if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<APIError>,
+}
+
+/// Selects the proposal to prove in a block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProofTarget {
+    /// Index of the proposal in the block.
+    EntryIndex(u32),
+    /// sha256 of the proposed data, i.e., the proposal Id.
+    DataHash(ids::Id),
+}
+
+/// Fetches the proof that a proposal is part of a block,
+/// to check with [`verify_inclusion_proof`](verify_inclusion_proof).
+/// # Errors
+/// Errors on failed (de)serialization or an http failure.
+pub async fn get_inclusion_proof(
+    http_rpc: &str,
+    url_path: &str,
+    block_id: &ids::Id,
+    target: &ProofTarget,
+) -> io::Result<GetInclusionProofResponse> {
+    log::info!("get_inclusion_proof {http_rpc} with {url_path}");
+
+    let mut m = HashMap::new();
+    m.insert("blockId".to_string(), block_id.to_string());
+    match target {
+        ProofTarget::EntryIndex(index) => m.insert("entryIndex".to_string(), index.to_string()),
+        ProofTarget::DataHash(data_hash) => m.insert("dataHash".to_string(), data_hash.to_string()),
+    };
+
+    let data = jsonrpc::RequestWithParamsHashMapArray {
+        method: String::from("timestampvm.getInclusionProof"),
+        params: Some(vec![m]),
+        ..Default::default()
+    };
+
+    let d = data.encode_json()?;
+    let rb = http_manager::post_non_tls(http_rpc, url_path, &d).await?;
+
+    serde_json::from_slice(&rb).map_err(|e| {
+        Error::new(
+            ErrorKind::Other,
+            format!("failed get_inclusion_proof '{e}'"),
+        )
+    })
+}
+
+/// Checks that the data is part of the block with the proven Id, without a node:
+/// the data and its signature, if any, hash to a Merkle leaf, the path leads from the
+/// leaf to the Merkle root, and the header with that root hashes to the block Id.
+/// Whether the block is accepted is up to the caller, e.g., by trusting the block Id.
+/// Signatures are checked for the given chain.
+/// # Errors
+/// Fails with `InvalidData` if any check fails.
+pub fn verify_inclusion_proof(
+    chain_id: &ids::Id,
+    data: &[u8],
+    proof: &crate::api::chain_handlers::GetInclusionProofResponse,
+) -> io::Result<()> {
+    if ids::Id::sha256(data) != proof.data_hash {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("data does not hash to {}", proof.data_hash),
+        ));
+    }
+
+    let entry = match &proof.signature {
+        Some(sig) => Entry::new_signed(data.to_vec(), sig.clone()),
+        None => Entry::new(data.to_vec()),
+    };
+    entry.verify_signature(chain_id)?;
+
+    if !merkle::verify(
+        &entry.leaf_hash()?,
+        proof.entry_index as usize,
+        proof.entry_count as usize,
+        &proof.path,
+        &proof.merkle_root,
+    ) {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "path does not lead from entry {} to merkle root {}",
+                proof.entry_index, proof.merkle_root
+            ),
+        ));
+    }
+
+    let block_id = Block::compute_id(
+        proof.parent_id,
+        proof.height,
+        proof.timestamp,
+        proof.merkle_root,
+    )?;
+    if block_id != proof.block_id {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("header hashes to block {block_id}, not {}", proof.block_id),
+        ));
+    }
+    Ok(())
+}
+
+/// Represents the RPC response for API `get_upgrades`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GetUpgradesResponse {
+    pub jsonrpc: String,
+    pub id: u32,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<crate::api::chain_handlers::GetUpgradesResponse>,
+
+    /// Returns non-empty if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<APIError>,
+}
+
+/// Fetches the upgrade schedule and the active rules.
+/// # Errors
+/// Errors on failed (de)serialization or an http failure.
+pub async fn get_upgrades(http_rpc: &str, url_path: &str) -> io::Result<GetUpgradesResponse> {
+    log::info!("get_upgrades {http_rpc} with {url_path}");
+
+    let data = jsonrpc::RequestWithParamsArray {
+        method: String::from("timestampvm.getUpgrades"),
+        ..Default::default()
+    };
+
+    let d = data.encode_json()?;
+    let rb = http_manager::post_non_tls(http_rpc, url_path, &d).await?;
+
+    serde_json::from_slice(&rb)
+        .map_err(|e| Error::new(ErrorKind::Other, format!("failed get_upgrades '{e}'")))
+}
+
+/// Represents the RPC response for API `verify_chain`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct VerifyChainResponse {
+    pub jsonrpc: String,
+    pub id: u32,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<crate::state::integrity::Report>,
+
+    /// Returns non-empty if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<APIError>,
+}
+
+/// Checks the stored chain of the node, via the admin path
+/// (e.g., "/ext/bc/[CHAIN ID]/admin").
+/// # Errors
+/// Errors on failed (de)serialization or an http failure.
+pub async fn verify_chain(http_rpc: &str, url_path: &str) -> io::Result<VerifyChainResponse> {
+    log::info!("verify_chain {http_rpc} with {url_path}");
+
+    let data = jsonrpc::RequestWithParamsArray {
+        method: String::from("timestampvm.verifyChain"),
+        ..Default::default()
+    };
+
+    let d = data.encode_json()?;
+    let rb = http_manager::post_non_tls(http_rpc, url_path, &d).await?;
+
+    serde_json::from_slice(&rb)
+        .map_err(|e| Error::new(ErrorKind::Other, format!("failed verify_chain '{e}'")))
+}
+
 /// Represents the error (if any) for APIs.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct APIError {
@@ -0,0 +1,711 @@
+//! Implements a reusable client for timestampvm APIs, built once per chain.
+
+use std::{fmt, io, time::Duration};
+
+use crate::{
+    api::{
+        self,
+        chain_handlers::{
+            BlockWithId, GetBlockArgs, GetBlockByHeightArgs, GetBlockResponse, GetBlocksArgs,
+            GetBlocksResponse, GetInclusionProofArgs, GetInclusionProofResponse,
+            GetProposalStatusArgs, GetProposalStatusResponse, GetUpgradesResponse,
+            LastAcceptedResponse, ProposeBlockArgs, ProposeBlockResponse,
+        },
+    },
+    block::signature::ProposalSignature,
+};
+use avalanche_types::ids;
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE},
+    StatusCode,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::{APIError, ProofTarget};
+
+/// Default time limit of a single request, including reading the response.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Default limit on the size of a response body.
+pub const DEFAULT_MAX_RESPONSE_BYTES: usize = 64 * 1024 * 1024;
+
+/// Decides how failed requests are retried.
+/// Only transport failures, timeouts, and HTTP 429 or 5xx responses are retried,
+/// since JSON-RPC errors are answers from the VM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Number of retries after the first attempt. Zero disables retries.
+    pub max_retries: u32,
+    /// Wait before the first retry, doubled after each retry.
+    pub initial_backoff: Duration,
+    /// Upper bound of the wait between two attempts.
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Disables retries.
+    #[must_use]
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the wait before the given retry, starting from zero.
+    #[must_use]
+    pub fn backoff(&self, retry: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2_u32.saturating_pow(retry))
+            .min(self.max_backoff)
+    }
+}
+
+/// Represents why a [`TimestampVmClient`](TimestampVmClient) call failed.
+#[derive(Debug)]
+pub enum ClientError {
+    /// The request could not be sent or the response could not be read,
+    /// including timeouts, after all retries.
+    Transport(String),
+    /// The node answered with a non-success HTTP status, after all retries.
+    Http { status: u16, body: String },
+    /// The response is not a valid JSON-RPC response.
+    Decode(String),
+    /// The VM answered with a JSON-RPC error, e.g., a full mempool
+    /// ([`MEMPOOL_FULL_ERROR_CODE`](crate::api::chain_handlers::MEMPOOL_FULL_ERROR_CODE)).
+    Api(APIError),
+}
+
+impl ClientError {
+    /// Returns the JSON-RPC error code, if the VM answered with an error.
+    #[must_use]
+    pub fn api_code(&self) -> Option<i32> {
+        match self {
+            ClientError::Api(e) => Some(e.code),
+            _ => None,
+        }
+    }
+
+    fn is_retryable(&self) -> bool {
+        match self {
+            ClientError::Transport(_) => true,
+            ClientError::Http { status, .. } => {
+                *status == StatusCode::TOO_MANY_REQUESTS.as_u16() || *status >= 500
+            }
+            ClientError::Decode(_) | ClientError::Api(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Transport(e) => write!(f, "request failed: {e}"),
+            ClientError::Http { status, body } => {
+                write!(f, "unexpected HTTP status {status}: {body}")
+            }
+            ClientError::Decode(e) => write!(f, "failed to decode response: {e}"),
+            ClientError::Api(e) => write!(f, "API error {}: {}", e.code, e.message),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<ClientError> for io::Error {
+    fn from(e: ClientError) -> Self {
+        let kind = match &e {
+            ClientError::Transport(_) | ClientError::Http { .. } => io::ErrorKind::Other,
+            ClientError::Decode(_) => io::ErrorKind::InvalidData,
+            ClientError::Api(_) => io::ErrorKind::InvalidInput,
+        };
+        io::Error::new(kind, e)
+    }
+}
+
+/// Builds a [`TimestampVmClient`](TimestampVmClient).
+#[derive(Debug, Clone)]
+pub struct Builder {
+    endpoint: String,
+    chain: String,
+    tls: Option<bool>,
+    accept_invalid_certs: bool,
+    timeout: Duration,
+    retry: RetryPolicy,
+    max_response_bytes: usize,
+    headers: Vec<(String, String)>,
+}
+
+impl Builder {
+    /// Sets whether to use HTTPS, overriding the scheme of the endpoint.
+    #[must_use]
+    pub fn tls(mut self, tls: bool) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Accepts self-signed certificates, e.g., of a local node.
+    #[must_use]
+    pub fn accept_invalid_certs(mut self, accept: bool) -> Self {
+        self.accept_invalid_certs = accept;
+        self
+    }
+
+    /// Sets the time limit of a single attempt.
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    #[must_use]
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Sets the limit on the size of a response body, e.g., to fetch
+    /// full pages of large blocks. Larger responses fail with
+    /// [`ClientError::Decode`](ClientError::Decode) and are not retried.
+    #[must_use]
+    pub fn max_response_bytes(mut self, max_response_bytes: usize) -> Self {
+        self.max_response_bytes = max_response_bytes;
+        self
+    }
+
+    /// Adds a header to every request, e.g., for an authenticating proxy.
+    #[must_use]
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Creates the client.
+    /// # Errors
+    /// Fails if the endpoint or a header is invalid.
+    pub fn build(self) -> io::Result<TimestampVmClient> {
+        let endpoint = if self.endpoint.contains("://") {
+            self.endpoint.clone()
+        } else {
+            format!("http://{}", self.endpoint)
+        };
+        let mut url = reqwest::Url::parse(&endpoint).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid endpoint '{}': {e}", self.endpoint),
+            )
+        })?;
+        if let Some(tls) = self.tls {
+            url.set_scheme(if tls { "https" } else { "http" })
+                .map_err(|()| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("can't set the scheme of endpoint '{}'", self.endpoint),
+                    )
+                })?;
+        }
+        let url = url
+            .join(&format!("ext/bc/{}/rpc", self.chain))
+            .map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid chain '{}': {e}", self.chain),
+                )
+            })?;
+
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            let name = HeaderName::from_bytes(name.as_bytes()).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid header name '{name}': {e}"),
+                )
+            })?;
+            let value = HeaderValue::from_str(value).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid header value for '{name}': {e}"),
+                )
+            })?;
+            headers.append(name, value);
+        }
+
+        let http = reqwest::Client::builder()
+            .user_agent(concat!("timestampvm/", env!("CARGO_PKG_VERSION")))
+            .default_headers(headers)
+            .timeout(self.timeout)
+            .danger_accept_invalid_certs(self.accept_invalid_certs)
+            .build()
+            .map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("failed to build HTTP client: {e}"),
+                )
+            })?;
+
+        Ok(TimestampVmClient {
+            http,
+            url,
+            retry: self.retry,
+            max_response_bytes: self.max_response_bytes,
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct Request<'a, P> {
+    jsonrpc: &'static str,
+    id: u32,
+    method: String,
+    params: &'a [P],
+}
+
+#[derive(Deserialize)]
+struct Response<R> {
+    result: Option<R>,
+    error: Option<APIError>,
+}
+
+/// Calls the timestampvm APIs of one chain, at `[ENDPOINT]/ext/bc/[CHAIN]/rpc`.
+/// Cheap to clone, clones share the connection pool.
+///
+/// ```no_run
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// use std::time::Duration;
+/// use timestampvm::client::TimestampVmClient;
+///
+/// let client = TimestampVmClient::builder("http://127.0.0.1:9650", "timestampvm")
+///     .timeout(Duration::from_secs(5))
+///     .header("authorization", "Bearer token")
+///     .build()?;
+/// let id = client.propose_block(b"hello".to_vec()).await?.id;
+/// println!("{:?}", client.get_proposal_status(&id).await?.status);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct TimestampVmClient {
+    http: reqwest::Client,
+    url: reqwest::Url,
+    retry: RetryPolicy,
+    max_response_bytes: usize,
+}
+
+impl TimestampVmClient {
+    /// Starts building a client for the node endpoint (e.g., `http://127.0.0.1:9650`,
+    /// where a missing scheme means HTTP) and the chain Id or alias.
+    #[must_use]
+    pub fn builder(endpoint: impl Into<String>, chain: impl Into<String>) -> Builder {
+        Builder {
+            endpoint: endpoint.into(),
+            chain: chain.into(),
+            tls: None,
+            accept_invalid_certs: false,
+            timeout: DEFAULT_TIMEOUT,
+            retry: RetryPolicy::default(),
+            max_response_bytes: DEFAULT_MAX_RESPONSE_BYTES,
+            headers: Vec::new(),
+        }
+    }
+
+    /// Returns the URL the requests are sent to.
+    #[must_use]
+    pub fn url(&self) -> &str {
+        self.url.as_str()
+    }
+
+    /// Pings the VM.
+    /// # Errors
+    /// See [`ClientError`](ClientError).
+    pub async fn ping(&self) -> Result<api::PingResponse, ClientError> {
+        self.call::<(), _>("ping", None).await
+    }
+
+    /// Proposes arbitrary data.
+    /// # Errors
+    /// See [`ClientError`](ClientError). Proposing data that is already pending fails with
+    /// [`MEMPOOL_DUPLICATE_ERROR_CODE`](crate::api::chain_handlers::MEMPOOL_DUPLICATE_ERROR_CODE),
+    /// unless an earlier attempt of the same call may have reached the node.
+    pub async fn propose_block(&self, data: Vec<u8>) -> Result<ProposeBlockResponse, ClientError> {
+        let args = ProposeBlockArgs {
+            data,
+            scheme: None,
+            public_key: None,
+            signature: None,
+        };
+        self.propose(&args).await
+    }
+
+    /// Proposes arbitrary data signed by the proposer.
+    /// # Errors
+    /// See [`propose_block`](TimestampVmClient::propose_block).
+    pub async fn propose_signed_block(
+        &self,
+        data: Vec<u8>,
+        signature: &ProposalSignature,
+    ) -> Result<ProposeBlockResponse, ClientError> {
+        let args = ProposeBlockArgs {
+            data,
+            scheme: Some(signature.scheme),
+            public_key: Some(signature.public_key.clone()),
+            signature: Some(signature.signature.clone()),
+        };
+        self.propose(&args).await
+    }
+
+    /// Proposing is not idempotent: an attempt that failed on the way back may have
+    /// reached the node, so a duplicate answer to a retry means the data was proposed.
+    async fn propose(&self, args: &ProposeBlockArgs) -> Result<ProposeBlockResponse, ClientError> {
+        let duplicate = i32::try_from(api::chain_handlers::MEMPOOL_DUPLICATE_ERROR_CODE).ok();
+        match self.call_with_retries("proposeBlock", Some(args)).await {
+            (Err(e), retries) if retries > 0 && e.api_code() == duplicate => {
+                log::info!("proposeBlock retried after reaching the node ({e})");
+                Ok(ProposeBlockResponse {
+                    success: true,
+                    id: ids::Id::sha256(&args.data),
+                })
+            }
+            (res, _) => res,
+        }
+    }
+
+    /// Fetches the last accepted block Id.
+    /// # Errors
+    /// See [`ClientError`](ClientError).
+    pub async fn last_accepted(&self) -> Result<ids::Id, ClientError> {
+        let resp: LastAcceptedResponse = self.call::<(), _>("lastAccepted", None).await?;
+        Ok(resp.id)
+    }
+
+    /// Fetches the block.
+    /// # Errors
+    /// See [`ClientError`](ClientError).
+    pub async fn get_block(&self, id: &ids::Id) -> Result<GetBlockResponse, ClientError> {
+        let args = GetBlockArgs { id: id.to_string() };
+        self.call("getBlock", Some(&args)).await
+    }
+
+    /// Fetches the accepted block at the height.
+    /// # Errors
+    /// See [`ClientError`](ClientError).
+    pub async fn get_block_by_height(&self, height: u64) -> Result<BlockWithId, ClientError> {
+        let args = GetBlockByHeightArgs { height };
+        self.call("getBlockByHeight", Some(&args)).await
+    }
+
+    /// Fetches a page of accepted blocks in ascending height order.
+    /// # Errors
+    /// See [`ClientError`](ClientError).
+    pub async fn get_blocks(
+        &self,
+        start_height: u64,
+        limit: Option<u32>,
+    ) -> Result<GetBlocksResponse, ClientError> {
+        let args = GetBlocksArgs {
+            start_height,
+            limit,
+        };
+        self.call("getBlocks", Some(&args)).await
+    }
+
+    /// Fetches the status of a proposal.
+    /// # Errors
+    /// See [`ClientError`](ClientError).
+    pub async fn get_proposal_status(
+        &self,
+        id: &ids::Id,
+    ) -> Result<GetProposalStatusResponse, ClientError> {
+        let args = GetProposalStatusArgs { id: id.to_string() };
+        self.call("getProposalStatus", Some(&args)).await
+    }
+
+    /// Fetches the upgrade schedule.
+    /// # Errors
+    /// See [`ClientError`](ClientError).
+    pub async fn get_upgrades(&self) -> Result<GetUpgradesResponse, ClientError> {
+        self.call::<(), _>("getUpgrades", None).await
+    }
+
+    /// Fetches the proof that a proposal is part of a block,
+    /// to check with [`verify_inclusion_proof`](super::verify_inclusion_proof).
+    /// # Errors
+    /// See [`ClientError`](ClientError).
+    pub async fn get_inclusion_proof(
+        &self,
+        block_id: &ids::Id,
+        target: &ProofTarget,
+    ) -> Result<GetInclusionProofResponse, ClientError> {
+        let (entry_index, data_hash) = match target {
+            ProofTarget::EntryIndex(index) => (Some(*index), None),
+            ProofTarget::DataHash(data_hash) => (None, Some(data_hash.to_string())),
+        };
+        let args = GetInclusionProofArgs {
+            block_id: block_id.to_string(),
+            entry_index,
+            data_hash,
+        };
+        self.call("getInclusionProof", Some(&args)).await
+    }
+
+    /// Calls the method with the optional params, retrying per the [`RetryPolicy`](RetryPolicy).
+    /// # Errors
+    /// See [`ClientError`](ClientError).
+    pub async fn call<P, R>(&self, method: &str, params: Option<&P>) -> Result<R, ClientError>
+    where
+        P: Serialize,
+        R: DeserializeOwned,
+    {
+        self.call_with_retries(method, params).await.0
+    }
+
+    /// Calls the method like [`call`](TimestampVmClient::call),
+    /// and also returns the number of retries it took.
+    async fn call_with_retries<P, R>(
+        &self,
+        method: &str,
+        params: Option<&P>,
+    ) -> (Result<R, ClientError>, u32)
+    where
+        P: Serialize,
+        R: DeserializeOwned,
+    {
+        let req = Request {
+            jsonrpc: "2.0",
+            id: 1,
+            method: format!("timestampvm.{method}"),
+            params: params.map_or(&[], std::slice::from_ref),
+        };
+        let body = match serde_json::to_vec(&req) {
+            Ok(body) => body,
+            Err(e) => return (Err(ClientError::Decode(e.to_string())), 0),
+        };
+
+        let mut retry = 0;
+        loop {
+            match self.post(&body).await {
+                Err(e) if e.is_retryable() && retry < self.retry.max_retries => {
+                    let backoff = self.retry.backoff(retry);
+                    log::warn!("{method} failed ({e}), retrying in {backoff:?}");
+                    tokio::time::sleep(backoff).await;
+                    retry += 1;
+                }
+                Err(e) => return (Err(e), retry),
+                Ok(rb) => return (Self::decode(method, &rb), retry),
+            }
+        }
+    }
+
+    fn decode<R: DeserializeOwned>(method: &str, rb: &[u8]) -> Result<R, ClientError> {
+        let resp: Response<R> = serde_json::from_slice(rb)
+            .map_err(|e| ClientError::Decode(format!("{method}: {e}")))?;
+        match (resp.result, resp.error) {
+            (_, Some(e)) => Err(ClientError::Api(e)),
+            (Some(result), None) => Ok(result),
+            (None, None) => Err(ClientError::Decode(format!(
+                "{method}: neither result nor error"
+            ))),
+        }
+    }
+
+    async fn post(&self, body: &[u8]) -> Result<Vec<u8>, ClientError> {
+        let mut resp = self
+            .http
+            .post(self.url.clone())
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.to_vec())
+            .send()
+            .await
+            .map_err(|e| ClientError::Transport(e.to_string()))?;
+
+        // read in chunks, so that a misbehaving node can't exhaust the memory
+        let too_large = || {
+            ClientError::Decode(format!(
+                "response exceeds {} bytes",
+                self.max_response_bytes
+            ))
+        };
+        if resp
+            .content_length()
+            .is_some_and(|len| len > self.max_response_bytes as u64)
+        {
+            return Err(too_large());
+        }
+        let status = resp.status();
+        let mut rb = Vec::new();
+        while let Some(chunk) = resp
+            .chunk()
+            .await
+            .map_err(|e| ClientError::Transport(e.to_string()))?
+        {
+            if rb.len() + chunk.len() > self.max_response_bytes {
+                return Err(too_large());
+            }
+            rb.extend_from_slice(&chunk);
+        }
+        if !status.is_success() {
+            return Err(ClientError::Http {
+                status: status.as_u16(),
+                body: String::from_utf8_lossy(&rb).into_owned(),
+            });
+        }
+        Ok(rb)
+    }
+}
+
+/// `RUST_LOG=debug cargo test --package timestampvm --lib -- client::timestampvm_client::test_client --exact --show-output`
+#[tokio::test]
+#[allow(clippy::too_many_lines)]
+async fn test_client() {
+    use std::sync::{Arc, Mutex};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    // serves the canned responses in order, and records the requests
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let responses = vec![
+        (503, String::from("unavailable")),
+        (
+            200,
+            String::from(r#"{"jsonrpc":"2.0","result":{"success":true},"id":1}"#),
+        ),
+        (
+            200,
+            String::from(
+                r#"{"jsonrpc":"2.0","error":{"code":-32001,"message":"mempool full"},"id":1}"#,
+            ),
+        ),
+        (200, String::from("not json")),
+        (500, String::from("down")),
+        (503, String::from("unavailable")),
+        (
+            200,
+            String::from(
+                r#"{"jsonrpc":"2.0","error":{"code":-32002,"message":"data already in mempool"},"id":1}"#,
+            ),
+        ),
+        (
+            200,
+            String::from(
+                r#"{"jsonrpc":"2.0","error":{"code":-32002,"message":"data already in mempool"},"id":1}"#,
+            ),
+        ),
+        (200, "x".repeat(1024)),
+    ];
+    let recorded = requests.clone();
+    tokio::spawn(async move {
+        for (status, body) in responses {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 64 * 1024];
+            let n = conn.read(&mut buf).await.unwrap();
+            recorded
+                .lock()
+                .unwrap()
+                .push(String::from_utf8_lossy(&buf[..n]).into_owned());
+            let resp = format!(
+                "HTTP/1.1 {status} X\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            conn.write_all(resp.as_bytes()).await.unwrap();
+        }
+    });
+
+    let client = TimestampVmClient::builder(addr.to_string(), "timestampvm")
+        .header("x-api-key", "secret")
+        .retry(RetryPolicy {
+            max_retries: 1,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(1),
+        })
+        .build()
+        .unwrap();
+    assert_eq!(
+        client.url(),
+        format!("http://{addr}/ext/bc/timestampvm/rpc")
+    );
+
+    // retried after the 503
+    assert!(client.ping().await.unwrap().success);
+    {
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1].starts_with("POST /ext/bc/timestampvm/rpc"));
+        assert!(requests[1].contains("x-api-key: secret"));
+        assert!(requests[1].contains(r#""method":"timestampvm.ping","params":[]"#));
+    }
+
+    // JSON-RPC errors are typed, and not retried
+    let err = client.propose_block(vec![1]).await.unwrap_err();
+    assert_eq!(
+        err.api_code(),
+        Some(i32::try_from(api::chain_handlers::MEMPOOL_FULL_ERROR_CODE).unwrap())
+    );
+    assert!(requests.lock().unwrap()[2].contains(r#""params":[{"data":"AQ=="}]"#));
+    assert!(matches!(
+        client.ping().await.unwrap_err(),
+        ClientError::Decode(_)
+    ));
+
+    // retries are bounded
+    let err = TimestampVmClient::builder(addr.to_string(), "timestampvm")
+        .retry(RetryPolicy::none())
+        .build()
+        .unwrap()
+        .ping()
+        .await
+        .unwrap_err();
+    assert!(matches!(err, ClientError::Http { status: 500, .. }));
+    assert_eq!(io::Error::from(err).kind(), io::ErrorKind::Other);
+
+    // a retried proposal may have reached the node the first time
+    let resp = client.propose_block(vec![2]).await.unwrap();
+    assert!(resp.success);
+    assert_eq!(resp.id, ids::Id::sha256([2]));
+    let err = client.propose_block(vec![2]).await.unwrap_err();
+    assert_eq!(
+        err.api_code(),
+        Some(i32::try_from(api::chain_handlers::MEMPOOL_DUPLICATE_ERROR_CODE).unwrap())
+    );
+
+    // responses are bounded
+    let err = TimestampVmClient::builder(addr.to_string(), "timestampvm")
+        .max_response_bytes(512)
+        .build()
+        .unwrap()
+        .ping()
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("response exceeds 512 bytes"));
+
+    let policy = RetryPolicy::default();
+    assert_eq!(policy.backoff(0), Duration::from_millis(200));
+    assert_eq!(policy.backoff(2), Duration::from_millis(800));
+    assert_eq!(policy.backoff(100), policy.max_backoff);
+
+    let tls = TimestampVmClient::builder(
+        "127.0.0.1:9650",
+        "2wb1UXxAstB8ywwv4rU2rFCjLgXnhT44hbLPbwpQoGvFb2wRR7",
+    )
+    .tls(true)
+    .build()
+    .unwrap();
+    assert!(tls.url().starts_with("https://127.0.0.1:9650/ext/bc/2wb1"));
+}
@@ -0,0 +1,67 @@
+//! Implements the canonical binary encoding of persisted and consensus data,
+//! following the avalanche linear codec layout: a big-endian `u16` codec version,
+//! then each field in declaration order, with big-endian integers, fixed-size Ids
+//! as raw bytes, and variable-length bytes and lists prefixed by a `u32` length.
+//!
+//! Data encoded as JSON before the binary codec existed always starts with `{`,
+//! which no codec version starts with, so decoders can tell both apart.
+
+use std::io::{self, Error, ErrorKind};
+
+use avalanche_types::{errors, packer::Packer};
+
+/// Current codec version, prefixed to every encoded value.
+pub const VERSION: u16 = 0;
+
+/// Limits the size of a single encoded value.
+pub const MAX_SIZE: usize = u32::MAX as usize;
+
+/// Returns "true" if the bytes were encoded as JSON before the binary codec existed.
+#[must_use]
+pub fn is_legacy_json(d: &[u8]) -> bool {
+    d.first() == Some(&b'{')
+}
+
+/// Creates a packer with the codec version already written.
+/// # Errors
+/// Fails if the version can't be packed.
+pub fn new_packer(initial_cap: usize) -> io::Result<Packer> {
+    let packer = Packer::new(MAX_SIZE, initial_cap);
+    packer.pack_u16(VERSION).map_err(to_io_error)?;
+    Ok(packer)
+}
+
+/// Creates a packer over the encoded bytes, positioned after the codec version.
+/// # Errors
+/// Fails if the version is missing or not supported.
+pub fn load_packer(d: &[u8]) -> io::Result<Packer> {
+    let packer = Packer::load_bytes_for_unpack(d.len(), d);
+    let version = packer.unpack_u16().map_err(to_io_error)?;
+    if version != VERSION {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("unsupported codec version {version}, expected {VERSION}"),
+        ));
+    }
+    Ok(packer)
+}
+
+/// Ensures the packer consumed all the bytes, so that every value has a single encoding.
+/// # Errors
+/// Fails if there are trailing bytes.
+pub fn ensure_consumed(packer: &Packer) -> io::Result<()> {
+    let trailing = packer.bytes_len() - packer.get_offset();
+    if trailing > 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("{trailing} trailing byte(s) after the encoded value"),
+        ));
+    }
+    Ok(())
+}
+
+/// Converts packer errors into I/O errors.
+#[must_use]
+pub fn to_io_error(e: errors::Error) -> io::Error {
+    Error::new(ErrorKind::InvalidData, e)
+}
@@ -0,0 +1,201 @@
+//! Defines the timestampvm chain config, passed as the config bytes to
+//! `initialize` (i.e., the chain config file of `AvalancheGo`).
+
+use std::{
+    fmt,
+    io::{self, Error, ErrorKind},
+};
+
+use crate::{
+    block, mempool,
+    state::{cache, processing, rejected},
+    upgrade::Rules,
+};
+use serde::{Deserialize, Serialize};
+
+/// Default limit on how much data a user can propose.
+pub const DEFAULT_PROPOSE_LIMIT_BYTES: usize = 1024 * 1024;
+
+/// Default limit on how far ahead of the local time a block timestamp can be.
+pub const DEFAULT_MAX_FUTURE_BLOCK_TIME_SECS: u64 = 60 * 60;
+
+/// Represents the VM config. Every field is optional in the JSON,
+/// and missing fields take their default values.
+/// The block limits are the base [`Rules`](crate::upgrade::Rules),
+/// which upgrades can override from their activation on.
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct VmConfig {
+    /// Limits how much data a single proposal can carry.
+    pub propose_limit_bytes: usize,
+    /// Limits the encoded size of a block.
+    pub block_size_limit: usize,
+    /// Limits how far ahead of the local time a block timestamp can be, in seconds.
+    pub max_future_block_time_secs: u64,
+
+    /// Maximum number of pending proposals.
+    pub mempool_max_count: usize,
+    /// Maximum total size of pending proposals.
+    pub mempool_max_bytes: usize,
+    /// What happens when a new proposal does not fit in the mempool.
+    pub mempool_eviction_policy: mempool::EvictionPolicy,
+    /// How many times a proposal of rejected blocks is returned to the mempool
+    /// before it is dropped. Set to 0 to drop them right away.
+    pub max_requeues: u32,
+
+    /// Maximum number of verified blocks that are not accepted or rejected yet.
+    pub max_processing_blocks: usize,
+    /// Maximum number of rejected blocks kept in memory. Rejected blocks are
+    /// never persisted. Set to 0 to drop them right away.
+    pub max_rejected_blocks: usize,
+    /// How long a rejected block is kept in memory, in seconds.
+    pub rejected_block_ttl_secs: u64,
+    /// Maximum number of decoded accepted blocks kept in memory.
+    /// Set to 0 to read every block from the db.
+    pub accepted_cache_size: usize,
+
+    /// Serves the operator-only handlers at the `admin` path.
+    /// Off by default, since the path can walk the whole chain on request.
+    pub admin_api_enabled: bool,
+}
+
+impl Default for VmConfig {
+    fn default() -> Self {
+        Self {
+            propose_limit_bytes: DEFAULT_PROPOSE_LIMIT_BYTES,
+            block_size_limit: block::DEFAULT_BLOCK_SIZE_LIMIT,
+            max_future_block_time_secs: DEFAULT_MAX_FUTURE_BLOCK_TIME_SECS,
+
+            mempool_max_count: mempool::DEFAULT_MAX_COUNT,
+            mempool_max_bytes: mempool::DEFAULT_MAX_BYTES,
+            mempool_eviction_policy: mempool::EvictionPolicy::default(),
+            max_requeues: mempool::DEFAULT_MAX_REQUEUES,
+
+            max_processing_blocks: processing::DEFAULT_MAX_PROCESSING_BLOCKS,
+            max_rejected_blocks: rejected::DEFAULT_MAX_REJECTED_BLOCKS,
+            rejected_block_ttl_secs: rejected::DEFAULT_REJECTED_BLOCK_TTL_SECS,
+            accepted_cache_size: cache::DEFAULT_ACCEPTED_CACHE_SIZE,
+
+            admin_api_enabled: false,
+        }
+    }
+}
+
+impl VmConfig {
+    /// Encodes the config to JSON bytes.
+    /// # Errors
+    /// Fails if `Self` can't be serialized
+    pub fn to_vec(&self) -> io::Result<Vec<u8>> {
+        serde_json::to_vec(&self).map_err(|e| {
+            Error::new(
+                ErrorKind::Other,
+                format!("failed to serialize VmConfig to JSON bytes {e}"),
+            )
+        })
+    }
+
+    /// Decodes and validates the config from JSON bytes.
+    /// Empty bytes (i.e., no chain config) yield the default config.
+    /// # Errors
+    /// Fails if the bytes can't be deserialized or the config is invalid
+    pub fn from_slice<S>(d: S) -> io::Result<Self>
+    where
+        S: AsRef<[u8]>,
+    {
+        let d = d.as_ref();
+        if d.iter().all(u8::is_ascii_whitespace) {
+            return Ok(Self::default());
+        }
+
+        let config: Self = serde_json::from_slice(d).map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("failed to decode VmConfig {e}"),
+            )
+        })?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Ensures the limits are consistent with each other.
+    /// # Errors
+    /// Fails if any limit is zero, or if a proposal at the limit
+    /// can't fit in a block or in the mempool.
+    pub fn validate(&self) -> io::Result<()> {
+        Rules::from(self).validate()?;
+
+        if self.max_processing_blocks == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "max_processing_blocks must be positive",
+            ));
+        }
+        if self.mempool_max_count == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "mempool_max_count must be positive",
+            ));
+        }
+        if self.mempool_max_bytes < self.propose_limit_bytes {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "mempool_max_bytes {} can't fit a {}-byte proposal",
+                    self.mempool_max_bytes, self.propose_limit_bytes
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for VmConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = serde_json::to_string(&self).unwrap();
+        write!(f, "{s}")
+    }
+}
+
+/// `RUST_LOG=debug cargo test --package timestampvm --lib -- config::test_config --exact --show-output`
+#[test]
+fn test_config() {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    assert_eq!(VmConfig::from_slice([]).unwrap(), VmConfig::default());
+    assert_eq!(VmConfig::from_slice(" \n").unwrap(), VmConfig::default());
+    assert_eq!(VmConfig::from_slice("{}").unwrap(), VmConfig::default());
+
+    let config = VmConfig::from_slice(
+        r#"{"propose_limit_bytes":100,"block_size_limit":1000,"mempool_eviction_policy":"evict_oldest"}"#,
+    )
+    .unwrap();
+    assert_eq!(config.propose_limit_bytes, 100);
+    assert_eq!(config.block_size_limit, 1000);
+    assert_eq!(
+        config.mempool_eviction_policy,
+        mempool::EvictionPolicy::EvictOldest
+    );
+    assert_eq!(config.mempool_max_count, mempool::DEFAULT_MAX_COUNT);
+    assert!(!config.admin_api_enabled);
+    assert!(
+        VmConfig::from_slice(r#"{"admin_api_enabled":true}"#)
+            .unwrap()
+            .admin_api_enabled
+    );
+    assert_eq!(
+        VmConfig::from_slice(config.to_vec().unwrap()).unwrap(),
+        config
+    );
+
+    // typos are not silently ignored
+    assert!(VmConfig::from_slice(r#"{"propose_limit":100}"#).is_err());
+
+    // a proposal at the limit must fit in a block and in the mempool
+    assert!(VmConfig::from_slice(r#"{"propose_limit_bytes":0}"#).is_err());
+    assert!(VmConfig::from_slice(r#"{"propose_limit_bytes":100,"block_size_limit":100}"#).is_err());
+    assert!(VmConfig::from_slice(r#"{"propose_limit_bytes":100,"mempool_max_bytes":99}"#).is_err());
+    assert!(VmConfig::from_slice(r#"{"mempool_max_count":0}"#).is_err());
+}
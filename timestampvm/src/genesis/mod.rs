@@ -7,18 +7,48 @@ use std::{
     path::Path,
 };
 
+use crate::{
+    block::{self, Block},
+    upgrade::{RuleOverrides, Rules},
+};
+use avalanche_types::{choices, codec::serde::base64_bytes::Base64Bytes, ids};
 use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
 
 /// Represents the genesis data specific to the VM.
+/// Genesis files with only `data` (i.e., before the other fields existed) still parse.
+#[serde_as]
 #[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
 pub struct Genesis {
+    /// UTF-8 message of a single-proposal genesis block.
+    /// Must be empty if `entries` is set.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub data: String,
+    /// Unix second of the genesis block.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub timestamp: u64,
+    /// Seed payloads of the genesis block, base64-encoded.
+    #[serde_as(as = "Vec<Base64Bytes>")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub entries: Vec<Vec<u8>>,
+    /// Initial consensus rules. These take precedence over the chain config,
+    /// since every node of the chain shares the same genesis.
+    #[serde(default, skip_serializing_if = "RuleOverrides::is_empty")]
+    pub params: RuleOverrides,
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn is_zero(v: &u64) -> bool {
+    *v == 0
 }
 
 impl Default for Genesis {
     fn default() -> Self {
         Self {
             data: String::from("Hello from Rust VM!"),
+            timestamp: 0,
+            entries: Vec::new(),
+            params: RuleOverrides::default(),
         }
     }
 }
@@ -47,9 +77,73 @@ impl Genesis {
             .map_err(|e| Error::new(ErrorKind::Other, format!("failed to decode {e}")))
     }
 
+    /// Creates the genesis block, checking the seed payloads against the initial rules.
+    /// A genesis with `data` keeps the JSON encoding of the earlier versions, so that
+    /// existing chains keep their genesis block Id; one with `entries` is binary-encoded.
+    /// # Errors
+    /// Fails if both `data` and `entries` are set, or if the payloads
+    /// exceed the proposal or block size limits.
+    pub fn to_block(&self, rules: &Rules) -> io::Result<Block> {
+        if !self.data.is_empty() && !self.entries.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "genesis can't have both data and entries",
+            ));
+        }
+        if let Some(d) = self
+            .entries
+            .iter()
+            .find(|d| d.len() > rules.propose_limit_bytes)
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "genesis entry {}-byte exceeds the limit {}-byte",
+                    d.len(),
+                    rules.propose_limit_bytes
+                ),
+            ));
+        }
+
+        let blk = if self.entries.is_empty() {
+            Block::try_new(
+                ids::Id::empty(),
+                0,
+                self.timestamp,
+                self.data.as_bytes().to_vec(),
+                choices::status::Status::default(),
+            )?
+        } else {
+            Block::try_new_with_entries(
+                ids::Id::empty(),
+                0,
+                self.timestamp,
+                self.entries
+                    .iter()
+                    .cloned()
+                    .map(block::Entry::new)
+                    .collect(),
+                choices::status::Status::default(),
+            )?
+        };
+        if blk.bytes().len() > rules.block_size_limit {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "genesis block size {} exceeds the limit {}",
+                    blk.bytes().len(),
+                    rules.block_size_limit
+                ),
+            ));
+        }
+        Ok(blk)
+    }
+
     /// Persists the genesis to a file.
     /// # Errors
     /// Fails if the file can't be created, written to, or if `self` can't be serialized
+    /// # Panics
+    /// Panics if the file path has no parent directory.
     pub fn sync(&self, file_path: &str) -> io::Result<()> {
         log::info!("syncing genesis to '{}'", file_path);
 
@@ -77,3 +171,56 @@ impl fmt::Display for Genesis {
         write!(f, "{s}")
     }
 }
+
+/// `RUST_LOG=debug cargo test --package timestampvm --lib -- genesis::test_genesis --exact --show-output`
+#[test]
+fn test_genesis() {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    // older single-data genesis files
+    let legacy = br#"{"data":"hello"}"#;
+    let genesis = Genesis::from_slice(legacy).unwrap();
+    assert_eq!(genesis.data, "hello");
+    assert_eq!(genesis.timestamp, 0);
+    assert_eq!(genesis.to_vec().unwrap(), legacy.to_vec());
+    let blk = genesis.to_block(&Rules::default()).unwrap();
+    assert_eq!(blk.payloads(), vec![b"hello".as_slice()]);
+    let legacy_blk = br#"{"parent_id":"11111111111111111111111111111111LpoYY","height":0,"timestamp":0,"data":"0x68656c6c6f"}"#;
+    assert_eq!(blk.bytes(), legacy_blk.as_slice());
+    assert_eq!(blk.id(), ids::Id::sha256(legacy_blk));
+
+    let genesis = Genesis::from_slice(
+        r#"{"timestamp":1700000000,"entries":["AAEC","/w=="],"params":{"propose_limit_bytes":3}}"#,
+    )
+    .unwrap();
+    assert!(genesis.data.is_empty());
+    assert_eq!(genesis.entries, vec![vec![0, 1, 2], vec![0xff]]);
+    assert_eq!(genesis.params.propose_limit_bytes, Some(3));
+    assert_eq!(
+        Genesis::from_slice(genesis.to_vec().unwrap()).unwrap(),
+        genesis
+    );
+
+    let mut rules = Rules::default();
+    rules.apply(&genesis.params);
+    let blk = genesis.to_block(&rules).unwrap();
+    assert_eq!(blk.timestamp(), 1_700_000_000);
+    assert_eq!(blk.height(), 0);
+    assert!(!blk.is_legacy_json());
+    assert_eq!(
+        blk.payloads(),
+        vec![[0, 1, 2].as_slice(), [0xff].as_slice()]
+    );
+
+    rules.propose_limit_bytes = 2;
+    assert!(genesis.to_block(&rules).is_err());
+
+    let both = Genesis {
+        data: String::from("hello"),
+        ..genesis
+    };
+    assert!(both.to_block(&Rules::default()).is_err());
+}
@@ -0,0 +1,65 @@
+//! Defines the app gossip messages exchanged between timestampvm nodes,
+//! so that a proposal reaches the mempool of whichever node builds the next block.
+
+use std::io::{self, Error, ErrorKind};
+
+use crate::block::{signature::ProposalSignature, Entry};
+use avalanche_types::codec::serde::hex_0x_bytes::Hex0xBytes;
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+
+/// Carries a proposal submitted to one node to its peers.
+#[serde_as]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Message {
+    /// Proposed data, subject to the same limits as local `proposeBlock` calls.
+    #[serde_as(as = "Hex0xBytes")]
+    pub data: Vec<u8>,
+    /// Signature of the proposer, checked again by each receiving node.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<ProposalSignature>,
+}
+
+impl From<Entry> for Message {
+    fn from(entry: Entry) -> Self {
+        Self {
+            data: entry.data().to_vec(),
+            signature: entry.signature().cloned(),
+        }
+    }
+}
+
+impl From<Message> for Entry {
+    fn from(msg: Message) -> Self {
+        match msg.signature {
+            Some(sig) => Entry::new_signed(msg.data, sig),
+            None => Entry::new(msg.data),
+        }
+    }
+}
+
+impl Message {
+    /// Encodes the [`Message`](Message) to JSON in bytes.
+    /// # Errors
+    /// Errors if the message can't be serialized to JSON.
+    pub fn to_vec(&self) -> io::Result<Vec<u8>> {
+        serde_json::to_vec(&self).map_err(|e| {
+            Error::new(
+                ErrorKind::Other,
+                format!("failed to serialize gossip Message to JSON bytes {e}"),
+            )
+        })
+    }
+
+    /// Loads [`Message`](Message) from JSON bytes.
+    /// # Errors
+    /// Errors if the message can't be deserialized from JSON.
+    pub fn from_slice(d: impl AsRef<[u8]>) -> io::Result<Self> {
+        serde_json::from_slice(d.as_ref()).map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("failed to deserialize gossip Message from JSON {e}"),
+            )
+        })
+    }
+}
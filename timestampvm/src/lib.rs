@@ -13,12 +13,20 @@
 //!
 //! The major components are:
 //!
+//! * [`archive`](https://docs.rs/timestampvm/latest/timestampvm/archive): Exports and imports the accepted chain as a portable archive.
 //! * [`api`](https://docs.rs/timestampvm/latest/timestampvm/api): Implementation of timestampvm APIs.
 //! * [`bin/timestampvm`](https://github.com/ava-labs/timestampvm-rs/tree/main/timestampvm/src/bin/timestampvm): Command-line interface, and plugin server.
 //! * [`block`](https://docs.rs/timestampvm/latest/timestampvm/block): Implementation of [`snowman.Block`](https://pkg.go.dev/github.com/ava-labs/avalanchego/snow/consensus/snowman#Block) interface for timestampvm.
 //! * [`client`](https://docs.rs/timestampvm/latest/timestampvm/client): Implements client for timestampvm APIs.
+//! * [`codec`](https://docs.rs/timestampvm/latest/timestampvm/codec): Implements the binary encoding of blocks.
+//! * [`config`](https://docs.rs/timestampvm/latest/timestampvm/config): Defines timestampvm chain config.
 //! * [`genesis`](https://docs.rs/timestampvm/latest/timestampvm/genesis): Defines timestampvm genesis block.
+//! * [`gossip`](https://docs.rs/timestampvm/latest/timestampvm/gossip): Defines app gossip messages between timestampvm nodes.
+//! * [`merkle`](https://docs.rs/timestampvm/latest/timestampvm/merkle): Implements the Merkle tree blocks commit to over their entries.
+//! * [`metrics`](https://docs.rs/timestampvm/latest/timestampvm/metrics): Counts VM events for operators.
+//! * [`mempool`](https://docs.rs/timestampvm/latest/timestampvm/mempool): Implements the bounded mempool of proposed data.
 //! * [`state`](https://docs.rs/timestampvm/latest/timestampvm/state): Manages the virtual machine states.
+//! * [`upgrade`](https://docs.rs/timestampvm/latest/timestampvm/upgrade): Defines the network upgrade schedule.
 //! * [`vm`](https://docs.rs/timestampvm/latest/timestampvm/vm): Implementation of [`snowman.block.ChainVM`](https://pkg.go.dev/github.com/ava-labs/avalanchego/snow/engine/snowman/block#ChainVM) interface for timestampvm.
 //!
 //! ## Example
@@ -41,8 +49,16 @@
 #![deny(clippy::pedantic)]
 
 pub mod api;
+pub mod archive;
 pub mod block;
 pub mod client;
+pub mod codec;
+pub mod config;
 pub mod genesis;
+pub mod gossip;
+pub mod mempool;
+pub mod merkle;
+pub mod metrics;
 pub mod state;
+pub mod upgrade;
 pub mod vm;
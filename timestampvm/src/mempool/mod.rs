@@ -0,0 +1,252 @@
+//! Implements the bounded, deduplicating mempool of proposed entries.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt, io,
+};
+
+use crate::block::Entry;
+use avalanche_types::ids;
+use serde::{Deserialize, Serialize};
+
+/// Default maximum number of pending proposals.
+pub const DEFAULT_MAX_COUNT: usize = 4096;
+
+/// Default maximum total size of pending proposals.
+pub const DEFAULT_MAX_BYTES: usize = 64 * 1024 * 1024;
+
+/// Default number of times a proposal of rejected blocks is returned to the mempool.
+pub const DEFAULT_MAX_REQUEUES: u32 = 3;
+
+/// Decides what happens when a new proposal does not fit in the mempool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EvictionPolicy {
+    /// Rejects the new proposal with [`Error::Full`](Error::Full).
+    #[default]
+    RejectNew,
+    /// Evicts the oldest proposals until the new one fits.
+    EvictOldest,
+}
+
+/// Represents why a proposal was not added to the mempool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The same data is already pending.
+    Duplicate,
+    /// The mempool reached its count or byte limit.
+    Full,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Duplicate => write!(f, "data already in mempool"),
+            Error::Full => write!(f, "mempool full"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        let kind = match e {
+            Error::Duplicate => io::ErrorKind::AlreadyExists,
+            Error::Full => io::ErrorKind::Other,
+        };
+        io::Error::new(kind, e)
+    }
+}
+
+/// Returns the [`Error`](Error) wrapped in an I/O error, if any.
+#[must_use]
+pub fn error_of(e: &io::Error) -> Option<Error> {
+    e.get_ref()
+        .and_then(|inner| inner.downcast_ref::<Error>())
+        .copied()
+}
+
+/// Holds proposals that have not been put into a block yet, in arrival order,
+/// keyed by the sha256 of the data.
+/// Mempool is not persistent, so just keep in memory via Vm.
+#[derive(Debug, Clone)]
+pub struct Mempool {
+    max_count: usize,
+    max_bytes: usize,
+    policy: EvictionPolicy,
+
+    order: VecDeque<ids::Id>,
+    entries: HashMap<ids::Id, Entry>,
+    bytes: usize,
+}
+
+impl Default for Mempool {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_MAX_COUNT,
+            DEFAULT_MAX_BYTES,
+            EvictionPolicy::default(),
+        )
+    }
+}
+
+impl Mempool {
+    #[must_use]
+    pub fn new(max_count: usize, max_bytes: usize, policy: EvictionPolicy) -> Self {
+        Self {
+            max_count,
+            max_bytes,
+            policy,
+
+            order: VecDeque::new(),
+            entries: HashMap::new(),
+            bytes: 0,
+        }
+    }
+
+    /// Adds the entry and returns the Id of its data.
+    /// Only the data counts towards the byte limit.
+    /// # Errors
+    /// Fails with [`Error::Duplicate`](Error::Duplicate) if the data is already pending,
+    /// or with [`Error::Full`](Error::Full) if it doesn't fit under the limits.
+    pub fn add(&mut self, entry: Entry) -> Result<ids::Id, Error> {
+        let id = ids::Id::sha256(entry.data());
+        let size = entry.data().len();
+        if self.entries.contains_key(&id) {
+            return Err(Error::Duplicate);
+        }
+        if self.max_count == 0 || size > self.max_bytes {
+            return Err(Error::Full);
+        }
+
+        while self.order.len() >= self.max_count || self.bytes + size > self.max_bytes {
+            match self.policy {
+                EvictionPolicy::RejectNew => return Err(Error::Full),
+                EvictionPolicy::EvictOldest => {
+                    if let Some((evicted, _)) = self.pop_front() {
+                        log::info!("evicted {evicted} from full mempool");
+                    }
+                }
+            }
+        }
+
+        self.bytes += size;
+        self.order.push_back(id);
+        self.entries.insert(id, entry);
+        Ok(id)
+    }
+
+    /// Removes and returns the oldest pending entry with its Id.
+    pub fn pop_front(&mut self) -> Option<(ids::Id, Entry)> {
+        while let Some(id) = self.order.pop_front() {
+            if let Some(entry) = self.entries.remove(&id) {
+                self.bytes -= entry.data().len();
+                return Some((id, entry));
+            }
+        }
+        None
+    }
+
+    /// Puts back entries taken with [`pop_front`](Self::pop_front), in their
+    /// original order, e.g., when the block that carried them failed to build.
+    /// The limits are not checked, since the entries only take back their own room.
+    pub fn restore_front(&mut self, taken: Vec<(ids::Id, Entry)>) {
+        for (id, entry) in taken.into_iter().rev() {
+            if self.entries.contains_key(&id) {
+                continue;
+            }
+            self.bytes += entry.data().len();
+            self.order.push_front(id);
+            self.entries.insert(id, entry);
+        }
+    }
+
+    /// Returns the oldest pending entry with its Id, without removing it.
+    #[must_use]
+    pub fn front(&self) -> Option<(&ids::Id, &Entry)> {
+        self.order
+            .front()
+            .and_then(|id| self.entries.get(id).map(|entry| (id, entry)))
+    }
+
+    /// Returns "true" if the data with the given Id is pending.
+    #[must_use]
+    pub fn contains(&self, id: &ids::Id) -> bool {
+        self.entries.contains_key(id)
+    }
+
+    /// Returns the number of pending proposals.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns "true" if there's no pending proposal.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the total size of pending proposals.
+    #[must_use]
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+}
+
+/// `RUST_LOG=debug cargo test --package timestampvm --lib -- mempool::test_mempool --exact --show-output`
+#[test]
+fn test_mempool() {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let mut mempool = Mempool::new(2, 10, EvictionPolicy::RejectNew);
+    assert!(mempool.is_empty());
+
+    let id1 = mempool.add(Entry::new(vec![1; 4])).unwrap();
+    assert_eq!(id1, ids::Id::sha256(vec![1; 4]));
+    assert_eq!(mempool.add(Entry::new(vec![1; 4])), Err(Error::Duplicate));
+
+    // byte limit
+    assert_eq!(mempool.add(Entry::new(vec![2; 7])), Err(Error::Full));
+    assert_eq!(mempool.add(Entry::new(vec![2; 11])), Err(Error::Full));
+
+    // count limit
+    let id2 = mempool.add(Entry::new(vec![2; 6])).unwrap();
+    assert_eq!(mempool.add(Entry::new(vec![3; 1])), Err(Error::Full));
+    assert_eq!(mempool.len(), 2);
+    assert_eq!(mempool.bytes(), 10);
+
+    let io_err: io::Error = Error::Full.into();
+    assert_eq!(error_of(&io_err), Some(Error::Full));
+    assert!(io_err.to_string().contains("mempool full"));
+
+    assert_eq!(mempool.front(), Some((&id1, &Entry::new(vec![1; 4]))));
+    assert_eq!(mempool.pop_front(), Some((id1, Entry::new(vec![1; 4]))));
+    assert!(!mempool.contains(&id1));
+    assert!(mempool.contains(&id2));
+
+    // taken entries go back to the front, in order
+    let taken = vec![mempool.pop_front().unwrap()];
+    mempool.add(Entry::new(vec![1; 4])).unwrap();
+    mempool.restore_front(taken);
+    assert_eq!(mempool.len(), 2);
+    assert_eq!(mempool.bytes(), 10);
+    assert_eq!(mempool.pop_front(), Some((id2, Entry::new(vec![2; 6]))));
+    assert_eq!(mempool.pop_front(), Some((id1, Entry::new(vec![1; 4]))));
+
+    // oldest entries make room for new ones
+    let mut mempool = Mempool::new(2, 10, EvictionPolicy::EvictOldest);
+    let id1 = mempool.add(Entry::new(vec![1; 4])).unwrap();
+    let id2 = mempool.add(Entry::new(vec![2; 4])).unwrap();
+    let id3 = mempool.add(Entry::new(vec![3; 4])).unwrap();
+    assert!(!mempool.contains(&id1));
+    let id4 = mempool.add(Entry::new(vec![4; 6])).unwrap();
+    assert!(!mempool.contains(&id2));
+    assert_eq!(mempool.pop_front(), Some((id3, Entry::new(vec![3; 4]))));
+    assert_eq!(mempool.pop_front(), Some((id4, Entry::new(vec![4; 6]))));
+    assert!(mempool.pop_front().is_none());
+}
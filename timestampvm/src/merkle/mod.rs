@@ -0,0 +1,152 @@
+//! Implements the binary Merkle tree that blocks commit to over their entries.
+//!
+//! The tree follows [RFC 9162](https://www.rfc-editor.org/rfc/rfc9162#section-2.1):
+//! leaves are hashed as `sha256(0x00 || leaf)` and interior nodes as
+//! `sha256(0x01 || left || right)`, and a tree of `n` leaves splits at the largest
+//! power of two smaller than `n`. The distinct prefixes keep a leaf from being
+//! passed off as an interior node, and the split avoids duplicating odd leaves.
+
+use avalanche_types::ids;
+
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+/// Returns the hash of a leaf.
+#[must_use]
+pub fn leaf_hash(d: &[u8]) -> ids::Id {
+    let mut b = Vec::with_capacity(1 + d.len());
+    b.push(LEAF_PREFIX);
+    b.extend_from_slice(d);
+    ids::Id::sha256(b)
+}
+
+fn node_hash(left: &ids::Id, right: &ids::Id) -> ids::Id {
+    let mut b = Vec::with_capacity(1 + 2 * ids::LEN);
+    b.push(NODE_PREFIX);
+    b.extend_from_slice(left.as_ref());
+    b.extend_from_slice(right.as_ref());
+    ids::Id::sha256(b)
+}
+
+/// Returns the largest power of two smaller than `n`, for `n > 1`.
+fn split(n: usize) -> usize {
+    let mut k = 1;
+    while k << 1 < n {
+        k <<= 1;
+    }
+    k
+}
+
+/// Returns the root over the leaf hashes.
+/// The root of an empty tree is the sha256 of empty bytes.
+#[must_use]
+pub fn root(leaves: &[ids::Id]) -> ids::Id {
+    match leaves.len() {
+        0 => ids::Id::sha256([]),
+        1 => leaves[0],
+        n => {
+            let k = split(n);
+            node_hash(&root(&leaves[..k]), &root(&leaves[k..]))
+        }
+    }
+}
+
+/// Returns the inclusion path of the leaf at `index`, from the leaf up to the root.
+/// Returns "None" if the index is out of range.
+#[must_use]
+pub fn proof(leaves: &[ids::Id], index: usize) -> Option<Vec<ids::Id>> {
+    if index >= leaves.len() {
+        return None;
+    }
+
+    let (mut lo, mut hi, mut index) = (0, leaves.len(), index);
+    let mut siblings = Vec::new();
+    while hi - lo > 1 {
+        let k = split(hi - lo);
+        if index < k {
+            siblings.push(root(&leaves[lo + k..hi]));
+            hi = lo + k;
+        } else {
+            siblings.push(root(&leaves[lo..lo + k]));
+            lo += k;
+            index -= k;
+        }
+    }
+    // siblings were collected from the root down
+    siblings.reverse();
+    Some(siblings)
+}
+
+/// Checks that the leaf hash is at `index` of a tree of `count` leaves with the given root.
+/// The root does not commit to `count`, so a valid path proves the leaf is part of
+/// the tree, while its position is only as trustworthy as the given `count`.
+#[must_use]
+pub fn verify(
+    leaf: &ids::Id,
+    index: usize,
+    count: usize,
+    path: &[ids::Id],
+    root: &ids::Id,
+) -> bool {
+    if index >= count {
+        return false;
+    }
+
+    // "fn" and "sn" of RFC 9162 section 2.1.3.2
+    let (mut node, mut last) = (index, count - 1);
+    let mut r = *leaf;
+    for p in path {
+        if last == 0 {
+            return false;
+        }
+        if node & 1 == 1 || node == last {
+            r = node_hash(p, &r);
+            while node & 1 == 0 && node != 0 {
+                node >>= 1;
+                last >>= 1;
+            }
+        } else {
+            r = node_hash(&r, p);
+        }
+        node >>= 1;
+        last >>= 1;
+    }
+    last == 0 && r == *root
+}
+
+/// `RUST_LOG=debug cargo test --package timestampvm --lib -- merkle::test_merkle --exact --show-output`
+#[test]
+fn test_merkle() {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    assert_eq!(root(&[]), ids::Id::sha256([]));
+    let leaf = leaf_hash(b"a");
+    assert_eq!(root(&[leaf]), leaf);
+    assert_ne!(leaf, ids::Id::sha256(b"a"));
+
+    // RFC 9162 shape: 3 leaves split as (0, 1), 2
+    let leaves: Vec<ids::Id> = (0..3_u8).map(|i| leaf_hash(&[i])).collect();
+    assert_eq!(
+        root(&leaves),
+        node_hash(&node_hash(&leaves[0], &leaves[1]), &leaves[2])
+    );
+
+    for count in 1..=9 {
+        let leaves: Vec<ids::Id> = (0..count).map(|i| leaf_hash(&[i])).collect();
+        let r = root(&leaves);
+        let count = leaves.len();
+        for (index, leaf) in leaves.iter().enumerate() {
+            let path = proof(&leaves, index).unwrap();
+            assert!(verify(leaf, index, count, &path, &r), "{index}/{count}");
+
+            // wrong index, leaf or root
+            assert!(!verify(leaf, index + 1, count, &path, &r));
+            assert!(!verify(&leaf_hash(b"x"), index, count, &path, &r));
+            assert!(!verify(leaf, index, count, &path, &leaf_hash(b"x")));
+        }
+        assert!(proof(&leaves, count).is_none());
+    }
+}
@@ -0,0 +1,59 @@
+//! Counts VM events for operators, served by the admin `getMetrics` API.
+//!
+//! Counters are plain atomics rather than Prometheus collectors, since the
+//! `avalanche_types` process metrics need its `subnet_metrics` feature.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+/// Holds the counters, shared through [`State`](crate::state::State).
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// Blocks rejected by consensus.
+    pub rejected_blocks: AtomicU64,
+    /// Proposals of rejected blocks returned to the mempool.
+    pub requeued_proposals: AtomicU64,
+    /// Proposals of rejected blocks not returned, since they were already
+    /// accepted, processing or pending.
+    pub requeue_skipped_proposals: AtomicU64,
+    /// Proposals of rejected blocks dropped after too many rejections,
+    /// or because the mempool was full.
+    pub requeue_dropped_proposals: AtomicU64,
+    /// Block reads served from the accepted block cache.
+    pub accepted_cache_hits: AtomicU64,
+    /// Block reads that missed the accepted block cache and hit the db.
+    pub accepted_cache_misses: AtomicU64,
+}
+
+impl Metrics {
+    /// Increments the counter by one.
+    pub fn inc(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the current values.
+    #[must_use]
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            rejected_blocks: self.rejected_blocks.load(Ordering::Relaxed),
+            requeued_proposals: self.requeued_proposals.load(Ordering::Relaxed),
+            requeue_skipped_proposals: self.requeue_skipped_proposals.load(Ordering::Relaxed),
+            requeue_dropped_proposals: self.requeue_dropped_proposals.load(Ordering::Relaxed),
+            accepted_cache_hits: self.accepted_cache_hits.load(Ordering::Relaxed),
+            accepted_cache_misses: self.accepted_cache_misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Values of the [`Metrics`](Metrics) at one point in time.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    pub rejected_blocks: u64,
+    pub requeued_proposals: u64,
+    pub requeue_skipped_proposals: u64,
+    pub requeue_dropped_proposals: u64,
+    pub accepted_cache_hits: u64,
+    pub accepted_cache_misses: u64,
+}
@@ -0,0 +1,179 @@
+//! Caches decoded accepted blocks, so hot paths skip the db reads and decodes.
+
+use std::collections::{BTreeMap, HashMap};
+
+use avalanche_types::ids;
+
+use crate::block::Block;
+
+/// Default maximum number of accepted blocks kept in the cache.
+pub const DEFAULT_ACCEPTED_CACHE_SIZE: usize = 256;
+
+/// Least recently used accepted blocks, plus the last accepted block.
+#[derive(Debug, Clone)]
+pub struct AcceptedCache {
+    capacity: usize,
+    /// Increases on every use, orders the entries from least to most recently used.
+    tick: u64,
+    blocks: HashMap<ids::Id, (u64, Block)>,
+    recency: BTreeMap<u64, ids::Id>,
+    last_accepted: Option<Block>,
+}
+
+impl Default for AcceptedCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_ACCEPTED_CACHE_SIZE)
+    }
+}
+
+impl AcceptedCache {
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            tick: 0,
+            blocks: HashMap::new(),
+            recency: BTreeMap::new(),
+            last_accepted: None,
+        }
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Returns the cached block, marking it as the most recently used.
+    pub fn get(&mut self, blk_id: &ids::Id) -> Option<Block> {
+        let tick = self.next_tick();
+        let (used, blk) = self.blocks.get_mut(blk_id)?;
+        self.recency.remove(used);
+        self.recency.insert(tick, *blk_id);
+        *used = tick;
+        Some(blk.clone())
+    }
+
+    /// Caches the block, evicting the least recently used one if full.
+    pub fn insert(&mut self, block: Block) {
+        if self.capacity == 0 {
+            return;
+        }
+        let blk_id = block.id();
+        self.remove(&blk_id);
+        while self.blocks.len() >= self.capacity {
+            let Some((_, lru_id)) = self.recency.pop_first() else {
+                break;
+            };
+            self.blocks.remove(&lru_id);
+        }
+
+        let tick = self.next_tick();
+        self.recency.insert(tick, blk_id);
+        self.blocks.insert(blk_id, (tick, block));
+    }
+
+    /// Drops the block from the cache, including the last accepted pointer.
+    pub fn remove(&mut self, blk_id: &ids::Id) {
+        self.evict(blk_id);
+        if self.last_accepted.as_ref().map(Block::id) == Some(*blk_id) {
+            self.last_accepted = None;
+        }
+    }
+
+    /// Drops the block from the cache, keeping the last accepted pointer.
+    pub fn evict(&mut self, blk_id: &ids::Id) {
+        if let Some((used, _)) = self.blocks.remove(blk_id) {
+            self.recency.remove(&used);
+        }
+    }
+
+    /// Drops every cached block and the last accepted pointer.
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+        self.recency.clear();
+        self.last_accepted = None;
+    }
+
+    #[must_use]
+    pub fn last_accepted(&self) -> Option<&Block> {
+        self.last_accepted.as_ref()
+    }
+
+    /// Caches the newly accepted block and points to it as the last accepted one.
+    pub fn set_last_accepted(&mut self, block: Block) {
+        self.insert(block.clone());
+        self.last_accepted = Some(block);
+    }
+
+    /// Forgets the last accepted pointer, keeping the cached blocks.
+    pub fn unset_last_accepted(&mut self) {
+        self.last_accepted = None;
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+}
+
+/// `RUST_LOG=debug cargo test --package timestampvm --lib -- state::cache::test_accepted_cache --exact --show-output`
+#[test]
+fn test_accepted_cache() {
+    use avalanche_types::choices::status::Status;
+
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let blks: Vec<Block> = (0..4_u8)
+        .map(|i| {
+            Block::try_new(ids::Id::empty(), u64::from(i), 0, vec![i], Status::Accepted).unwrap()
+        })
+        .collect();
+
+    let mut cache = AcceptedCache::new(3);
+    for blk in &blks[..3] {
+        cache.insert(blk.clone());
+    }
+    // using blks[0] makes blks[1] the least recently used
+    assert_eq!(cache.get(&blks[0].id()), Some(blks[0].clone()));
+    cache.insert(blks[3].clone());
+    assert_eq!(cache.len(), 3);
+    assert!(cache.get(&blks[1].id()).is_none());
+    assert!(cache.get(&blks[0].id()).is_some());
+    assert!(cache.get(&blks[2].id()).is_some());
+    assert_eq!(cache.blocks.len(), cache.recency.len());
+
+    cache.set_last_accepted(blks[1].clone());
+    assert_eq!(cache.last_accepted(), Some(&blks[1]));
+    assert_eq!(cache.len(), 3);
+    assert!(cache.get(&blks[3].id()).is_none(), "evicted");
+
+    cache.remove(&blks[1].id());
+    assert!(cache.last_accepted().is_none());
+    assert!(cache.get(&blks[1].id()).is_none());
+    assert_eq!(cache.len(), 2);
+
+    cache.set_last_accepted(blks[0].clone());
+    cache.evict(&blks[0].id());
+    assert_eq!(cache.last_accepted(), Some(&blks[0]));
+    assert!(cache.get(&blks[0].id()).is_none());
+
+    cache.set_last_accepted(blks[3].clone());
+    cache.unset_last_accepted();
+    assert!(cache.last_accepted().is_none());
+    assert!(cache.get(&blks[3].id()).is_some());
+
+    cache.clear();
+    assert!(cache.is_empty() && cache.recency.is_empty());
+
+    let mut disabled = AcceptedCache::new(0);
+    disabled.insert(blks[0].clone());
+    assert!(disabled.is_empty());
+}
@@ -0,0 +1,390 @@
+//! Checks that the chain stored in [`State`](super::State) is consistent.
+
+use std::{collections::HashMap, io};
+
+use avalanche_types::{
+    choices::status::Status,
+    ids,
+    subnet::{self, rpc::database::BoxedDatabase},
+};
+use serde::{Deserialize, Serialize};
+
+use super::{block_with_status_key, BlockWithStatus, State, DELIMITER, STATUS_PREFIX};
+use crate::block::Block;
+
+/// Describes a single inconsistency found by [`verify_chain`](verify_chain).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(
+    tag = "kind",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum Issue {
+    /// A block of the accepted chain is not stored.
+    MissingBlock { block_id: ids::Id, height: u64 },
+    /// A stored record or its block bytes can't be decoded.
+    Undecodable { block_id: ids::Id, error: String },
+    /// The Id recomputed from the stored bytes differs from the Id it is stored under.
+    IdMismatch {
+        block_id: ids::Id,
+        computed_id: ids::Id,
+    },
+    /// The stored status is wrong: blocks of the accepted chain must be accepted,
+    /// and other stored blocks rejected.
+    WrongStatus {
+        block_id: ids::Id,
+        status: Status,
+        expected: Status,
+    },
+    /// A block does not sit right above its parent.
+    HeightGap {
+        block_id: ids::Id,
+        height: u64,
+        parent_height: u64,
+    },
+    /// A block is older than its parent.
+    TimestampDecrease {
+        block_id: ids::Id,
+        timestamp: u64,
+        parent_timestamp: u64,
+    },
+    /// A block at height zero has a parent.
+    InvalidGenesis {
+        block_id: ids::Id,
+        parent_id: ids::Id,
+    },
+    /// The height index points to another block, or to none.
+    HeightIndexMismatch {
+        height: u64,
+        block_id: ids::Id,
+        indexed: Option<ids::Id>,
+    },
+}
+
+/// Result of [`verify_chain`](verify_chain).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    /// "None" if nothing has been accepted yet.
+    pub last_accepted: Option<ids::Id>,
+    pub last_accepted_height: Option<u64>,
+    /// Lowest height the walk reached, zero unless the walk stopped
+    /// at a missing or undecodable block.
+    pub lowest_height: Option<u64>,
+    /// Number of accepted chain blocks checked.
+    pub checked_blocks: u64,
+    /// Number of stored blocks off the accepted chain checked.
+    pub other_blocks: u64,
+    pub issues: Vec<Issue>,
+}
+
+impl Report {
+    /// Returns "true" if no issue was found.
+    #[must_use]
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Walks the accepted chain from the last accepted block down to genesis through
+/// the parent Ids, then checks every other stored block.
+///
+/// Each block must be stored under the Id recomputed from its bytes, sit one height
+/// above its parent, be no older than its parent, match the height index, and have
+/// the accepted status. Blocks off the accepted chain must have the rejected status.
+/// # Errors
+/// Fails only if the db can't be read, inconsistencies are reported in the [`Report`](Report).
+pub async fn verify_chain(state: &State) -> io::Result<Report> {
+    let mut report = Report::default();
+    if !state.has_last_accepted_block().await? {
+        return Ok(report);
+    }
+    let index_complete = state.is_height_index_complete().await?;
+
+    // reads through a clone, so that the walk never holds the db lock
+    let db = state.db.read().await.clone();
+
+    let mut blk_id = state.get_last_accepted_block_id().await?;
+    report.last_accepted = Some(blk_id);
+
+    // accepted chain block Ids and their heights
+    let mut canonical = HashMap::new();
+    let mut child: Option<Block> = None;
+    loop {
+        let record = match db.get(&block_with_status_key(&blk_id)).await {
+            Ok(record) => record,
+            Err(e) if subnet::rpc::errors::is_not_found(&e) => {
+                report.issues.push(Issue::MissingBlock {
+                    block_id: blk_id,
+                    height: child.as_ref().map_or(0, |c| c.height().saturating_sub(1)),
+                });
+                break;
+            }
+            Err(e) => return Err(e),
+        };
+        let (blk, status) = match decode(&record) {
+            Ok(decoded) => decoded,
+            Err(e) => {
+                report.issues.push(Issue::Undecodable {
+                    block_id: blk_id,
+                    error: e.to_string(),
+                });
+                break;
+            }
+        };
+        canonical.insert(blk_id, blk.height());
+        report.checked_blocks += 1;
+        report.lowest_height = Some(blk.height());
+        if report.last_accepted_height.is_none() {
+            report.last_accepted_height = Some(blk.height());
+        }
+
+        check_record(&blk_id, &blk, status, Status::Accepted, &mut report);
+        if let Some(child) = &child {
+            check_parent(child, &blk, &mut report);
+        }
+        match state.get_block_id_at_height(blk.height()).await {
+            Ok(indexed) if indexed == blk_id => {}
+            Ok(indexed) => report.issues.push(Issue::HeightIndexMismatch {
+                height: blk.height(),
+                block_id: blk_id,
+                indexed: Some(indexed),
+            }),
+            Err(_) if index_complete => report.issues.push(Issue::HeightIndexMismatch {
+                height: blk.height(),
+                block_id: blk_id,
+                indexed: None,
+            }),
+            Err(_) => {}
+        }
+
+        if blk.height() == 0 {
+            if blk.parent_id() != ids::Id::empty() {
+                report.issues.push(Issue::InvalidGenesis {
+                    block_id: blk_id,
+                    parent_id: blk.parent_id(),
+                });
+            }
+            break;
+        }
+        blk_id = blk.parent_id();
+        if let Some(parent_height) = canonical.get(&blk_id) {
+            // parents loop back into the walked chain
+            report.issues.push(Issue::HeightGap {
+                block_id: blk.id(),
+                height: blk.height(),
+                parent_height: *parent_height,
+            });
+            break;
+        }
+        child = Some(blk);
+    }
+
+    check_other_blocks(&db, &canonical, &mut report).await?;
+
+    if report.is_ok() {
+        log::info!(
+            "verified {} accepted and {} other block(s)",
+            report.checked_blocks,
+            report.other_blocks
+        );
+    } else {
+        log::warn!("found {} chain issue(s)", report.issues.len());
+    }
+    Ok(report)
+}
+
+/// Checks the stored blocks off the accepted chain, which are only ever rejected.
+async fn check_other_blocks(
+    db: &BoxedDatabase,
+    canonical: &HashMap<ids::Id, u64>,
+    report: &mut Report,
+) -> io::Result<()> {
+    let mut iter = db
+        .new_iterator_with_prefix(&[STATUS_PREFIX, DELIMITER])
+        .await?;
+    while iter.next().await? {
+        let blk_id = ids::Id::from_slice(&iter.key().await?[2..]);
+        if canonical.contains_key(&blk_id) {
+            continue;
+        }
+        report.other_blocks += 1;
+
+        let (blk, status) = match decode(iter.value().await?) {
+            Ok(decoded) => decoded,
+            Err(e) => {
+                report.issues.push(Issue::Undecodable {
+                    block_id: blk_id,
+                    error: e.to_string(),
+                });
+                continue;
+            }
+        };
+        check_record(&blk_id, &blk, status, Status::Rejected, report);
+    }
+    iter.error().await?;
+    iter.release().await;
+
+    Ok(())
+}
+
+/// Checks that the block is stored under its Id and with the expected status.
+fn check_record(
+    blk_id: &ids::Id,
+    blk: &Block,
+    status: Status,
+    expected: Status,
+    report: &mut Report,
+) {
+    if blk.id() != *blk_id {
+        report.issues.push(Issue::IdMismatch {
+            block_id: *blk_id,
+            computed_id: blk.id(),
+        });
+    }
+    if status != expected {
+        report.issues.push(Issue::WrongStatus {
+            block_id: *blk_id,
+            status,
+            expected,
+        });
+    }
+}
+
+/// Checks that the child block sits right above its parent, and is no older.
+fn check_parent(child: &Block, parent: &Block, report: &mut Report) {
+    if child.height() != parent.height() + 1 {
+        report.issues.push(Issue::HeightGap {
+            block_id: child.id(),
+            height: child.height(),
+            parent_height: parent.height(),
+        });
+    }
+    if child.timestamp() < parent.timestamp() {
+        report.issues.push(Issue::TimestampDecrease {
+            block_id: child.id(),
+            timestamp: child.timestamp(),
+            parent_timestamp: parent.timestamp(),
+        });
+    }
+}
+
+fn decode(record: &[u8]) -> io::Result<(Block, Status)> {
+    let blk_status = BlockWithStatus::from_slice(record)?;
+    let blk = Block::from_slice(&blk_status.block_bytes)?;
+    Ok((blk, blk_status.status))
+}
+
+/// `RUST_LOG=debug cargo test --package timestampvm --lib -- state::integrity::test_verify_chain --exact --show-output`
+#[tokio::test]
+async fn test_verify_chain() {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let mut state = State::default();
+    assert_eq!(verify_chain(&state).await.unwrap(), Report::default());
+
+    let mut blks = Vec::new();
+    let mut parent_id = ids::Id::empty();
+    for height in 0..3_u64 {
+        let blk = Block::try_new(
+            parent_id,
+            height,
+            height * 10,
+            vec![u8::try_from(height).unwrap()],
+            Status::Accepted,
+        )
+        .unwrap();
+        state.write_accepted_block(&blk).await.unwrap();
+        parent_id = blk.id();
+        blks.push(blk);
+    }
+    let rejected = Block::try_new(blks[1].id(), 2, 20, vec![9], Status::Rejected).unwrap();
+    state.write_block(&rejected).await.unwrap();
+
+    let report = verify_chain(&state).await.unwrap();
+    assert!(report.is_ok(), "{report:?}");
+    assert_eq!(report.last_accepted, Some(blks[2].id()));
+    assert_eq!(report.last_accepted_height, Some(2));
+    assert_eq!(report.lowest_height, Some(0));
+    assert_eq!((report.checked_blocks, report.other_blocks), (3, 1));
+
+    // an older block on top, and an accepted block that lost its status
+    let older = Block::try_new(blks[2].id(), 3, 5, vec![3], Status::Accepted).unwrap();
+    state.write_accepted_block(&older).await.unwrap();
+    let mut demoted = blks[1].clone();
+    demoted.set_status(Status::Rejected);
+    state.write_block(&demoted).await.unwrap();
+
+    // off-chain records that claim acceptance, or are stored under another Id
+    let stray = Block::try_new(blks[0].id(), 1, 10, vec![7], Status::Accepted).unwrap();
+    state.write_block(&stray).await.unwrap();
+    let wrong_key = ids::Id::sha256(b"wrong");
+    let record = BlockWithStatus {
+        block_bytes: rejected.bytes().to_vec(),
+        status: Status::Rejected,
+    }
+    .encode()
+    .unwrap();
+    state
+        .db
+        .write()
+        .await
+        .put(&block_with_status_key(&wrong_key), &record)
+        .await
+        .unwrap();
+
+    let report = verify_chain(&state).await.unwrap();
+    let mut expected = vec![
+        Issue::TimestampDecrease {
+            block_id: older.id(),
+            timestamp: 5,
+            parent_timestamp: 20,
+        },
+        Issue::WrongStatus {
+            block_id: blks[1].id(),
+            status: Status::Rejected,
+            expected: Status::Accepted,
+        },
+    ];
+    // off-chain records are scanned in key order
+    let mut off_chain = vec![
+        (
+            stray.id(),
+            Issue::WrongStatus {
+                block_id: stray.id(),
+                status: Status::Accepted,
+                expected: Status::Rejected,
+            },
+        ),
+        (
+            wrong_key,
+            Issue::IdMismatch {
+                block_id: wrong_key,
+                computed_id: rejected.id(),
+            },
+        ),
+    ];
+    off_chain.sort_by(|a, b| a.0.as_ref().cmp(b.0.as_ref()));
+    expected.extend(off_chain.into_iter().map(|(_, issue)| issue));
+    assert_eq!(report.issues, expected);
+    assert_eq!(report.checked_blocks, 4);
+
+    // a missing block stops the walk
+    state
+        .db
+        .write()
+        .await
+        .delete(&block_with_status_key(&blks[0].id()))
+        .await
+        .unwrap();
+    let report = verify_chain(&state).await.unwrap();
+    assert_eq!(report.lowest_height, Some(1));
+    assert!(report.issues.contains(&Issue::MissingBlock {
+        block_id: blks[0].id(),
+        height: 0,
+    }));
+    log::info!("report: {}", serde_json::to_string(&report).unwrap());
+}
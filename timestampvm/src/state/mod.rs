@@ -1,13 +1,22 @@
 //! Manages the virtual machine states.
 
+pub mod cache;
+pub mod integrity;
+pub mod processing;
+pub mod rejected;
+
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     io::{self, Error, ErrorKind},
     sync::Arc,
+    time::Instant,
 };
 
-use crate::block::Block;
-use avalanche_types::{choices, ids, subnet};
+use crate::{
+    block::{Block, Entry},
+    codec, metrics, upgrade,
+};
+use avalanche_types::{choices, ids, packer, subnet};
 use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
 
@@ -16,9 +25,33 @@ use tokio::sync::RwLock;
 pub struct State {
     pub db: Arc<RwLock<Box<dyn subnet::rpc::database::Database + Send + Sync>>>,
 
-    /// Maps block Id to Block.
-    /// Each element is verified but not yet accepted/rejected (e.g., preferred).
-    pub verified_blocks: Arc<RwLock<HashMap<ids::Id, Block>>>,
+    /// Blocks that are verified but not yet accepted/rejected (e.g., preferred).
+    pub verified_blocks: Arc<RwLock<processing::ProcessingTree>>,
+
+    /// Verified blocks dropped because they conflict with an accepted block,
+    /// kept until the engine rejects them, since it looks them up first.
+    pub pruned_blocks: Arc<RwLock<HashMap<ids::Id, Block>>>,
+
+    /// Rules that blocks are built and verified with.
+    pub schedule: upgrade::Schedule,
+
+    /// Id of this chain, which proposal signatures are bound to.
+    pub chain_id: ids::Id,
+
+    /// Recently used accepted blocks and the last accepted block, decoded.
+    pub accepted_cache: Arc<RwLock<cache::AcceptedCache>>,
+
+    /// Rejected blocks, kept in memory only and for a limited time.
+    pub recently_rejected: Arc<RwLock<rejected::RecentlyRejected>>,
+
+    /// Processing blocks that this node built, whose proposals go back
+    /// to the mempool if the block is rejected.
+    pub built_blocks: Arc<RwLock<HashSet<ids::Id>>>,
+
+    /// Proposals of rejected blocks, until the Vm returns them to the mempool.
+    pub rejected_entries: Arc<RwLock<Vec<Entry>>>,
+
+    pub metrics: Arc<metrics::Metrics>,
 }
 
 impl Default for State {
@@ -27,15 +60,41 @@ impl Default for State {
             db: Arc::new(RwLock::new(
                 subnet::rpc::database::memdb::Database::new_boxed(),
             )),
-            verified_blocks: Arc::new(RwLock::new(HashMap::new())),
+            verified_blocks: Arc::new(RwLock::new(processing::ProcessingTree::default())),
+            pruned_blocks: Arc::new(RwLock::new(HashMap::new())),
+            schedule: upgrade::Schedule::default(),
+            chain_id: ids::Id::empty(),
+            accepted_cache: Arc::new(RwLock::new(cache::AcceptedCache::default())),
+            recently_rejected: Arc::new(RwLock::new(rejected::RecentlyRejected::default())),
+            built_blocks: Arc::new(RwLock::new(HashSet::new())),
+            rejected_entries: Arc::new(RwLock::new(Vec::new())),
+            metrics: Arc::new(metrics::Metrics::default()),
         }
     }
 }
 
 const LAST_ACCEPTED_BLOCK_KEY: &[u8] = b"last_accepted_block";
 
+/// Set once every accepted height, from genesis up, has an entry under `HEIGHT_INDEX_PREFIX`.
+/// Only written together with the height 0 entry, by the genesis acceptance or
+/// [`repair_height_index`](State::repair_height_index), so that no gap can hide below it.
+const HEIGHT_INDEX_COMPLETE_KEY: &[u8] = b"height_index_complete";
+
+/// sha256 of the genesis bytes the database was created with.
+const GENESIS_HASH_KEY: &[u8] = b"genesis_hash";
+
+/// Set once the rejected blocks persisted by earlier versions are deleted.
+const REJECTED_COMPACTED_KEY: &[u8] = b"rejected_compacted";
+
+/// Maximum number of persisted blocks scanned per rejected block compaction batch.
+const COMPACT_REJECTED_BATCH_LEN: usize = 1024;
+
 const STATUS_PREFIX: u8 = 0x0;
 
+const HEIGHT_INDEX_PREFIX: u8 = 0x1;
+
+const PROPOSAL_INDEX_PREFIX: u8 = 0x2;
+
 const DELIMITER: u8 = b'/';
 
 /// Returns a vec of bytes used as a key for identifying blocks in state.
@@ -48,8 +107,30 @@ fn block_with_status_key(blk_id: &ids::Id) -> Vec<u8> {
     k
 }
 
+/// Returns a vec of bytes used as a key for identifying the accepted block at a height.
+/// '`HEIGHT_INDEX_PREFIX`' + '`BYTE_DELIMITER`' + [`height`] (big-endian)
+fn block_id_at_height_key(height: u64) -> Vec<u8> {
+    let mut k: Vec<u8> = Vec::with_capacity(8 + 2);
+    k.push(HEIGHT_INDEX_PREFIX);
+    k.push(DELIMITER);
+    k.extend_from_slice(&height.to_be_bytes());
+    k
+}
+
+/// Returns a vec of bytes used as a key for the accepted block that includes a proposal.
+/// '`PROPOSAL_INDEX_PREFIX`' + '`BYTE_DELIMITER`' + [`proposal_id`]
+fn proposal_key(proposal_id: &ids::Id) -> Vec<u8> {
+    let mut k: Vec<u8> = Vec::with_capacity(ids::LEN + 2);
+    k.push(PROPOSAL_INDEX_PREFIX);
+    k.push(DELIMITER);
+    k.extend_from_slice(&proposal_id.to_vec());
+    k
+}
+
 /// Wraps a [`Block`](crate::block::Block) and its status.
-/// This is the data format that [`State`](State) uses to persist blocks.
+/// This is the data format that [`State`](State) uses to persist blocks,
+/// encoded with the [`codec`](crate::codec) as
+/// `codec version (u16) | status (u32) | block bytes (u32 length + bytes)`.
 #[derive(Serialize, Deserialize, Clone)]
 struct BlockWithStatus {
     block_bytes: Vec<u8>,
@@ -58,23 +139,75 @@ struct BlockWithStatus {
 
 impl BlockWithStatus {
     fn encode(&self) -> io::Result<Vec<u8>> {
-        serde_json::to_vec(&self).map_err(|e| {
-            Error::new(
-                ErrorKind::Other,
-                format!("failed to serialize BlockStatus to JSON bytes: {e}"),
-            )
-        })
+        let packer =
+            codec::new_packer(packer::U16_LEN + packer::U32_LEN * 2 + self.block_bytes.len())?;
+        packer
+            .pack_u32(self.status.to_u32())
+            .map_err(codec::to_io_error)?;
+        packer
+            .pack_bytes_with_header(&self.block_bytes)
+            .map_err(codec::to_io_error)?;
+        Ok(packer.take_bytes().to_vec())
     }
 
+    /// Decodes the binary encoding, or the JSON encoding written before the binary codec existed.
     fn from_slice(d: impl AsRef<[u8]>) -> io::Result<Self> {
         let dd = d.as_ref();
-        serde_json::from_slice(dd).map_err(|e| {
-            Error::new(
-                ErrorKind::Other,
-                format!("failed to deserialize BlockStatus from JSON: {e}"),
-            )
+        if codec::is_legacy_json(dd) {
+            return serde_json::from_slice(dd).map_err(|e| {
+                Error::new(
+                    ErrorKind::Other,
+                    format!("failed to deserialize BlockStatus from JSON: {e}"),
+                )
+            });
+        }
+
+        let packer = codec::load_packer(dd)?;
+        let status = Self::unpack_status(&packer)?;
+        let block_bytes = packer
+            .unpack_bytes_with_header()
+            .map_err(codec::to_io_error)?;
+        codec::ensure_consumed(&packer)?;
+
+        Ok(Self {
+            block_bytes,
+            status,
         })
     }
+
+    /// Decodes only the status, without copying or decoding the block bytes.
+    fn status_from_slice(d: impl AsRef<[u8]>) -> io::Result<choices::status::Status> {
+        #[derive(Deserialize)]
+        struct StatusOnly {
+            status: choices::status::Status,
+        }
+
+        let dd = d.as_ref();
+        if codec::is_legacy_json(dd) {
+            let v: StatusOnly = serde_json::from_slice(dd).map_err(|e| {
+                Error::new(
+                    ErrorKind::Other,
+                    format!("failed to deserialize BlockStatus from JSON: {e}"),
+                )
+            })?;
+            return Ok(v.status);
+        }
+
+        let packer = codec::load_packer(dd)?;
+        Self::unpack_status(&packer)
+    }
+
+    fn unpack_status(packer: &packer::Packer) -> io::Result<choices::status::Status> {
+        match packer.unpack_u32().map_err(codec::to_io_error)? {
+            1 => Ok(choices::status::Status::Processing),
+            2 => Ok(choices::status::Status::Rejected),
+            3 => Ok(choices::status::Status::Accepted),
+            v => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("unknown block status {v}"),
+            )),
+        }
+    }
 }
 
 impl State {
@@ -90,7 +223,9 @@ impl State {
                     ErrorKind::Other,
                     format!("failed to put last accepted block: {e:?}"),
                 )
-            })
+            })?;
+        self.accepted_cache.write().await.unset_last_accepted();
+        Ok(())
     }
 
     /// Returns "true" if there's a last accepted block found.
@@ -111,6 +246,10 @@ impl State {
     /// # Errors
     /// Can fail if the db can't be read
     pub async fn get_last_accepted_block_id(&self) -> io::Result<ids::Id> {
+        if let Some(blk) = self.accepted_cache.read().await.last_accepted() {
+            return Ok(blk.id());
+        }
+
         let db = self.db.read().await;
         match db.get(LAST_ACCEPTED_BLOCK_KEY).await {
             Ok(d) => Ok(ids::Id::from_slice(&d)),
@@ -123,13 +262,75 @@ impl State {
         }
     }
 
+    /// Returns the last accepted block, from memory once it has been read or accepted.
+    /// # Errors
+    /// Can fail if the db can't be read, or if no block has been accepted yet
+    pub async fn get_last_accepted_block(&self) -> io::Result<Block> {
+        if let Some(blk) = self.accepted_cache.read().await.last_accepted() {
+            return Ok(blk.clone());
+        }
+
+        let blk = self
+            .get_block(&self.get_last_accepted_block_id().await?)
+            .await?;
+        let mut accepted_cache = self.accepted_cache.write().await;
+        // an acceptance since the read already moved the pointer
+        if accepted_cache.last_accepted().is_none() {
+            accepted_cache.set_last_accepted(blk.clone());
+        }
+        Ok(blk)
+    }
+
+    /// Persists the hash of the genesis bytes the chain was created with.
+    /// # Errors
+    /// Fails if the db can't be updated
+    pub async fn put_genesis_hash(&mut self, genesis_hash: &ids::Id) -> io::Result<()> {
+        let mut db = self.db.write().await;
+        db.put(GENESIS_HASH_KEY, &genesis_hash.to_vec())
+            .await
+            .map_err(|e| {
+                Error::new(
+                    ErrorKind::Other,
+                    format!("failed to put genesis hash: {e:?}"),
+                )
+            })
+    }
+
+    /// Returns the hash of the genesis bytes the chain was created with,
+    /// or "None" for databases created before it was recorded.
+    /// # Errors
+    /// Fails if the db can't be read
+    pub async fn get_genesis_hash(&self) -> io::Result<Option<ids::Id>> {
+        let db = self.db.read().await;
+        match db.get(GENESIS_HASH_KEY).await {
+            Ok(d) => Ok(Some(ids::Id::from_slice(&d))),
+            Err(e) => {
+                if subnet::rpc::errors::is_not_found(&e) {
+                    return Ok(None);
+                }
+                Err(e)
+            }
+        }
+    }
+
     /// Adds a block to "`verified_blocks`".
-    pub async fn add_verified(&mut self, block: &Block) {
+    /// # Errors
+    /// Fails if the number of verified but undecided blocks reached its limit.
+    pub async fn add_verified(&mut self, block: &Block) -> io::Result<()> {
         let blk_id = block.id();
-        log::info!("verified added {blk_id}");
 
         let mut verified_blocks = self.verified_blocks.write().await;
-        verified_blocks.insert(blk_id, block.clone());
+        if !verified_blocks.insert(block.clone()) {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!(
+                    "can't verify {blk_id}, {} blocks are already processing",
+                    verified_blocks.len()
+                ),
+            ));
+        }
+        log::info!("verified added {blk_id}");
+        Ok(())
     }
 
     /// Removes a block from "`verified_blocks`".
@@ -138,6 +339,34 @@ impl State {
         verified_blocks.remove(blk_id);
     }
 
+    /// Removes the accepted block from "`verified_blocks`", together with every
+    /// block that conflicts with it. The conflicting blocks move to "`pruned_blocks`"
+    /// until they are rejected.
+    pub async fn prune_verified(&mut self, accepted: &ids::Id) {
+        let pruned = self.verified_blocks.write().await.prune(accepted);
+        if pruned.is_empty() {
+            return;
+        }
+        log::info!(
+            "dropped {} block(s) conflicting with accepted {accepted}",
+            pruned.len()
+        );
+
+        let mut pruned_blocks = self.pruned_blocks.write().await;
+        pruned_blocks.extend(pruned.into_iter().map(|blk| (blk.id(), blk)));
+    }
+
+    /// Keeps a rejected block in memory, instead of persisting it.
+    pub async fn add_rejected(&mut self, block: &Block) {
+        let mut recently_rejected = self.recently_rejected.write().await;
+        recently_rejected.insert(block.clone(), Instant::now());
+        drop(recently_rejected);
+        self.pruned_blocks.write().await.remove(&block.id());
+
+        // the last accepted block stays, a rejection never moves it
+        self.accepted_cache.write().await.evict(&block.id());
+    }
+
     /// Returns "true" if the block Id has been already verified.
     pub async fn has_verified(&self, blk_id: &ids::Id) -> bool {
         let verified_blocks = self.verified_blocks.read().await;
@@ -149,7 +378,7 @@ impl State {
     /// Can fail if the block fails to serialize or if the db can't be updated
     pub async fn write_block(&mut self, block: &Block) -> io::Result<()> {
         let blk_id = block.id();
-        let blk_bytes = block.to_vec()?;
+        let blk_bytes = block.bytes().to_vec();
 
         let mut db = self.db.write().await;
 
@@ -161,7 +390,281 @@ impl State {
 
         db.put(&block_with_status_key(&blk_id), &blk_status_bytes)
             .await
-            .map_err(|e| Error::new(ErrorKind::Other, format!("failed to put block: {e:?}")))
+            .map_err(|e| Error::new(ErrorKind::Other, format!("failed to put block: {e:?}")))?;
+
+        // the status may have changed
+        self.accepted_cache.write().await.remove(&blk_id);
+        Ok(())
+    }
+
+    /// Persists an accepted block together with its height index entry, the proposal
+    /// index entries of its payloads and the last accepted block Id in a single batch,
+    /// so that they never disagree on disk.
+    /// # Errors
+    /// Can fail if the block fails to serialize or if the db can't be updated
+    pub async fn write_accepted_block(&mut self, block: &Block) -> io::Result<()> {
+        let blk_id = block.id();
+        let blk_status = BlockWithStatus {
+            block_bytes: block.bytes().to_vec(),
+            status: block.status(),
+        };
+        let blk_status_bytes = blk_status.encode()?;
+
+        let db = self.db.write().await;
+        let mut batch = db.new_batch().await?;
+        batch
+            .put(&block_with_status_key(&blk_id), &blk_status_bytes)
+            .await?;
+        batch
+            .put(&block_id_at_height_key(block.height()), &blk_id.to_vec())
+            .await?;
+        for d in block.payloads() {
+            batch
+                .put(&proposal_key(&ids::Id::sha256(d)), &blk_id.to_vec())
+                .await?;
+        }
+        batch.put(LAST_ACCEPTED_BLOCK_KEY, &blk_id.to_vec()).await?;
+        if block.height() == 0 {
+            // a chain indexed from genesis never needs a backfill
+            batch.put(HEIGHT_INDEX_COMPLETE_KEY, &[]).await?;
+        }
+        batch.write().await.map_err(|e| {
+            Error::new(
+                ErrorKind::Other,
+                format!("failed to write accepted block: {e:?}"),
+            )
+        })?;
+
+        self.accepted_cache
+            .write()
+            .await
+            .set_last_accepted(block.clone());
+        Ok(())
+    }
+
+    /// Returns "true" if every accepted block has been written to the height index.
+    /// # Errors
+    /// Fails if the db can't be read
+    pub async fn is_height_index_complete(&self) -> io::Result<bool> {
+        let db = self.db.read().await;
+        match db.has(HEIGHT_INDEX_COMPLETE_KEY).await {
+            Ok(found) => Ok(found),
+            Err(e) => Err(Error::new(
+                ErrorKind::Other,
+                format!("failed to load height index status: {e}"),
+            )),
+        }
+    }
+
+    /// Returns the accepted block Id at the given height.
+    /// # Errors
+    /// Fails with "index incomplete" if the height index has not been built yet,
+    /// or with "not found" if no block has been accepted at the height
+    pub async fn get_block_id_at_height(&self, height: u64) -> io::Result<ids::Id> {
+        if !self.is_height_index_complete().await? {
+            return Err(subnet::rpc::errors::Error::IndexIncomplete.to_err());
+        }
+
+        let db = self.db.read().await;
+        let d = db.get(&block_id_at_height_key(height)).await?;
+        Ok(ids::Id::from_slice(&d))
+    }
+
+    /// Returns up to `limit` accepted blocks from `start_height` in ascending height order,
+    /// stopping after the last accepted block.
+    /// # Errors
+    /// Fails if the height index is incomplete, or if a block in the range is missing
+    pub async fn get_accepted_blocks(
+        &self,
+        start_height: u64,
+        limit: usize,
+    ) -> io::Result<Vec<Block>> {
+        let last_accepted = self.get_last_accepted_block().await?;
+
+        let mut blocks = Vec::new();
+        let mut height = start_height;
+        while blocks.len() < limit && height <= last_accepted.height() {
+            let blk_id = self.get_block_id_at_height(height).await.map_err(|e| {
+                Error::new(
+                    e.kind(),
+                    format!("no accepted block at height {height}: {e}"),
+                )
+            })?;
+            blocks.push(self.get_block(&blk_id).await?);
+            height += 1;
+        }
+        Ok(blocks)
+    }
+
+    /// Backfills the height index and the proposal index for databases created
+    /// before they existed, by walking the parent links from the last accepted
+    /// block down to genesis. No-op if the index is already complete.
+    /// # Errors
+    /// Fails if any accepted block can't be read or if the db can't be updated
+    pub async fn repair_height_index(&mut self) -> io::Result<()> {
+        if self.is_height_index_complete().await? {
+            return Ok(());
+        }
+        if !self.has_last_accepted_block().await? {
+            // nothing accepted yet, genesis acceptance completes the index
+            return Ok(());
+        }
+
+        let last_accepted_blk_id = self.get_last_accepted_block_id().await?;
+        log::info!("repairing height index from last accepted block {last_accepted_blk_id}");
+
+        let db = self.db.read().await.clone();
+        let mut batch = db.new_batch().await?;
+
+        let mut blk_id = last_accepted_blk_id;
+        let mut indexed: u64 = 0;
+        loop {
+            let blk = self.get_block(&blk_id).await?;
+            batch
+                .put(&block_id_at_height_key(blk.height()), &blk_id.to_vec())
+                .await?;
+            for d in blk.payloads() {
+                batch
+                    .put(&proposal_key(&ids::Id::sha256(d)), &blk_id.to_vec())
+                    .await?;
+            }
+            indexed += 1;
+
+            if batch.size().await? > subnet::rpc::database::MAX_BATCH_SIZE {
+                batch.write().await?;
+                batch.reset().await;
+            }
+
+            if blk.height() == 0 {
+                break;
+            }
+            blk_id = blk.parent_id();
+        }
+
+        batch.put(HEIGHT_INDEX_COMPLETE_KEY, &[]).await?;
+        batch.write().await.map_err(|e| {
+            Error::new(
+                ErrorKind::Other,
+                format!("failed to write height index: {e:?}"),
+            )
+        })?;
+
+        log::info!("repaired height index with {indexed} block(s)");
+        Ok(())
+    }
+
+    /// Deletes the rejected blocks persisted before rejected blocks were kept in memory only,
+    /// scanning a bounded number of blocks at a time so that other db users are not blocked for long.
+    /// No-op once done. Returns the number of deleted blocks.
+    /// # Errors
+    /// Fails if the db can't be read or updated
+    pub async fn compact_rejected(&self) -> io::Result<usize> {
+        self.compact_rejected_in_batches(COMPACT_REJECTED_BATCH_LEN)
+            .await
+    }
+
+    async fn compact_rejected_in_batches(&self, batch_len: usize) -> io::Result<usize> {
+        let db = self.db.read().await.clone();
+        if db.has(REJECTED_COMPACTED_KEY).await? {
+            return Ok(0);
+        }
+
+        let prefix = [STATUS_PREFIX, DELIMITER];
+        let mut start = prefix.to_vec();
+        let mut deleted = 0;
+        loop {
+            let mut keys = Vec::new();
+            let mut next_start = None;
+            let mut scanned = 0;
+            let mut iter = db
+                .new_iterator_with_start_and_prefix(&start, &prefix)
+                .await?;
+            while iter.next().await? {
+                if scanned == batch_len {
+                    next_start = Some(iter.key().await?.to_vec());
+                    break;
+                }
+                scanned += 1;
+
+                // undecodable records are left for "verifyChain" to report
+                if let Ok(status) = BlockWithStatus::status_from_slice(iter.value().await?) {
+                    if status == choices::status::Status::Rejected {
+                        keys.push(iter.key().await?.to_vec());
+                    }
+                }
+            }
+            iter.error().await?;
+            iter.release().await;
+
+            let mut batch = db.new_batch().await?;
+            for k in &keys {
+                batch.delete(k).await?;
+            }
+            if next_start.is_none() {
+                batch.put(REJECTED_COMPACTED_KEY, &[]).await?;
+            }
+            batch.write().await.map_err(|e| {
+                Error::new(
+                    ErrorKind::Other,
+                    format!("failed to delete rejected blocks: {e:?}"),
+                )
+            })?;
+            deleted += keys.len();
+
+            match next_start {
+                Some(k) => {
+                    start = k;
+                    tokio::task::yield_now().await;
+                }
+                None => return Ok(deleted),
+            }
+        }
+    }
+
+    /// Returns the Id of the accepted block that includes the proposal with the given Id,
+    /// where the proposal Id is the sha256 of the proposed data.
+    /// # Errors
+    /// Fails if the db can't be read
+    pub async fn get_accepted_proposal_block_id(
+        &self,
+        proposal_id: &ids::Id,
+    ) -> io::Result<Option<ids::Id>> {
+        let db = self.db.read().await;
+        let k = proposal_key(proposal_id);
+        if !db.has(&k).await? {
+            return Ok(None);
+        }
+        let d = db.get(&k).await?;
+        Ok(Some(ids::Id::from_slice(&d)))
+    }
+
+    /// Returns the Id of a verified or accepted block that includes
+    /// the proposal with the given Id, if any.
+    /// # Errors
+    /// Fails if the db can't be read
+    pub async fn find_proposal_block_id(
+        &self,
+        proposal_id: &ids::Id,
+    ) -> io::Result<Option<ids::Id>> {
+        if let Some(blk_id) = self.find_verified_proposal_block_id(proposal_id).await {
+            return Ok(Some(blk_id));
+        }
+        self.get_accepted_proposal_block_id(proposal_id).await
+    }
+
+    /// Returns the Id of a verified but undecided block that includes
+    /// the proposal with the given Id, if any.
+    pub async fn find_verified_proposal_block_id(&self, proposal_id: &ids::Id) -> Option<ids::Id> {
+        let verified_blocks = self.verified_blocks.read().await;
+        let found = verified_blocks
+            .values()
+            .find(|blk| {
+                blk.payloads()
+                    .iter()
+                    .any(|d| ids::Id::sha256(d) == *proposal_id)
+            })
+            .map(Block::id);
+        found
     }
 
     /// Reads a block from the state storage using the `block_with_status_key`.
@@ -169,24 +672,45 @@ impl State {
     /// Can fail if the block is not found in the state storage, or if the block fails to deserialize
     pub async fn get_block(&self, blk_id: &ids::Id) -> io::Result<Block> {
         // check if the block exists in memory as previously verified.
-        let verified_blocks = self.verified_blocks.read().await;
-        if let Some(b) = verified_blocks.get(blk_id) {
+        // each guard is dropped right away, so that the db read below holds none
+        if let Some(b) = self.verified_blocks.read().await.get(blk_id) {
             return Ok(b.clone());
         }
+        if let Some(b) = self.pruned_blocks.read().await.get(blk_id) {
+            return Ok(b.clone());
+        }
+        if let Some(b) = self
+            .recently_rejected
+            .read()
+            .await
+            .get(blk_id, Instant::now())
+        {
+            return Ok(b.clone());
+        }
+        if let Some(b) = self.accepted_cache.write().await.get(blk_id) {
+            metrics::Metrics::inc(&self.metrics.accepted_cache_hits);
+            return Ok(b);
+        }
+        metrics::Metrics::inc(&self.metrics.accepted_cache_misses);
 
-        let db = self.db.read().await;
-
-        let blk_status_bytes = db.get(&block_with_status_key(blk_id)).await?;
+        let blk_status_bytes = {
+            let db = self.db.read().await;
+            db.get(&block_with_status_key(blk_id)).await?
+        };
         let blk_status = BlockWithStatus::from_slice(blk_status_bytes)?;
 
         let mut blk = Block::from_slice(&blk_status.block_bytes)?;
         blk.set_status(blk_status.status);
 
+        // only accepted blocks are final, others may still change status
+        if blk.status() == choices::status::Status::Accepted {
+            self.accepted_cache.write().await.insert(blk.clone());
+        }
         Ok(blk)
     }
 }
 
-/// RUST_LOG=debug cargo test --package timestampvm --lib -- state::test_state --exact --show-output
+/// `RUST_LOG=debug cargo test --package timestampvm --lib -- state::test_state --exact --show-output`
 #[tokio::test]
 async fn test_state() {
     let _ = env_logger::builder()
@@ -232,4 +756,260 @@ async fn test_state() {
 
     let read_blk = state.get_block(&blk1.id()).await.unwrap();
     assert_eq!(blk1, read_blk);
+
+    // records persisted as JSON before the binary codec existed
+    let legacy_blk = Block::try_new(
+        blk1.id(),
+        2,
+        blk1.timestamp() + 1,
+        random_manager::secure_bytes(10).unwrap(),
+        choices::status::Status::Rejected,
+    )
+    .unwrap();
+    let legacy_record = serde_json::to_vec(&BlockWithStatus {
+        block_bytes: legacy_blk.bytes().to_vec(),
+        status: choices::status::Status::Rejected,
+    })
+    .unwrap();
+    state
+        .db
+        .write()
+        .await
+        .put(&block_with_status_key(&legacy_blk.id()), &legacy_record)
+        .await
+        .unwrap();
+    let read_blk = state.get_block(&legacy_blk.id()).await.unwrap();
+    assert_eq!(read_blk, legacy_blk);
+    assert_eq!(read_blk.status(), choices::status::Status::Rejected);
+}
+
+/// `RUST_LOG=debug cargo test --package timestampvm --lib -- state::test_height_index --exact --show-output`
+#[tokio::test]
+async fn test_height_index() {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let mut state = State::default();
+
+    // simulates a database written before the height index existed
+    let mut parent_id = ids::Id::empty();
+    let mut blk_ids = Vec::new();
+    let mut datas = Vec::new();
+    for height in 0..5 {
+        let data = random_manager::secure_bytes(10).unwrap();
+        let blk = Block::try_new(
+            parent_id,
+            height,
+            height,
+            data.clone(),
+            choices::status::Status::Accepted,
+        )
+        .unwrap();
+        datas.push(data);
+        state.write_block(&blk).await.unwrap();
+        state.set_last_accepted_block(&blk.id()).await.unwrap();
+
+        parent_id = blk.id();
+        blk_ids.push(blk.id());
+    }
+    assert!(!state.is_height_index_complete().await.unwrap());
+    assert!(state.get_block_id_at_height(0).await.is_err());
+
+    state.repair_height_index().await.unwrap();
+    assert!(state.is_height_index_complete().await.unwrap());
+    for (height, blk_id) in blk_ids.iter().enumerate() {
+        let read_id = state.get_block_id_at_height(height as u64).await.unwrap();
+        assert_eq!(read_id, *blk_id);
+    }
+    assert!(state.get_block_id_at_height(5).await.is_err());
+    for (data, blk_id) in datas.iter().zip(blk_ids.iter()) {
+        let read_id = state
+            .get_accepted_proposal_block_id(&ids::Id::sha256(data))
+            .await
+            .unwrap();
+        assert_eq!(read_id, Some(*blk_id));
+    }
+
+    let blk5 = Block::try_new(
+        parent_id,
+        5,
+        5,
+        random_manager::secure_bytes(10).unwrap(),
+        choices::status::Status::Accepted,
+    )
+    .unwrap();
+    state.write_accepted_block(&blk5).await.unwrap();
+    assert_eq!(state.get_block_id_at_height(5).await.unwrap(), blk5.id());
+    assert_eq!(state.get_last_accepted_block_id().await.unwrap(), blk5.id());
+    assert_eq!(
+        state
+            .get_accepted_proposal_block_id(&ids::Id::sha256(blk5.data()))
+            .await
+            .unwrap(),
+        Some(blk5.id())
+    );
+    assert!(state
+        .get_accepted_proposal_block_id(&ids::Id::sha256([0; 10]))
+        .await
+        .unwrap()
+        .is_none());
+}
+
+/// `RUST_LOG=debug cargo test --package timestampvm --lib -- state::test_compact_rejected --exact --show-output`
+#[tokio::test]
+async fn test_compact_rejected() {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let mut state = State::default();
+    let accepted = Block::try_new(
+        ids::Id::empty(),
+        0,
+        0,
+        vec![0],
+        choices::status::Status::Accepted,
+    )
+    .unwrap();
+    state.write_block(&accepted).await.unwrap();
+
+    // persisted by earlier versions
+    let mut rejected = Vec::new();
+    for i in 1..=3 {
+        let blk = Block::try_new(
+            accepted.id(),
+            1,
+            1,
+            vec![i],
+            choices::status::Status::Rejected,
+        )
+        .unwrap();
+        state.write_block(&blk).await.unwrap();
+        rejected.push(blk);
+    }
+
+    // legacy JSON records are compacted as well
+    let legacy_blk = Block::try_new(
+        accepted.id(),
+        1,
+        1,
+        vec![4],
+        choices::status::Status::Rejected,
+    )
+    .unwrap();
+    let legacy_record = serde_json::to_vec(&BlockWithStatus {
+        block_bytes: legacy_blk.bytes().to_vec(),
+        status: choices::status::Status::Rejected,
+    })
+    .unwrap();
+    state
+        .db
+        .write()
+        .await
+        .put(&block_with_status_key(&legacy_blk.id()), &legacy_record)
+        .await
+        .unwrap();
+    rejected.push(legacy_blk);
+
+    // batches smaller than the number of blocks still visit every block
+    assert_eq!(state.compact_rejected_in_batches(2).await.unwrap(), 4);
+    assert_eq!(state.get_block(&accepted.id()).await.unwrap(), accepted);
+    for blk in &rejected {
+        let e = state.get_block(&blk.id()).await.unwrap_err();
+        assert!(subnet::rpc::errors::is_not_found(&e));
+    }
+
+    // rejected now are kept in memory only
+    state.add_rejected(&rejected[0]).await;
+    assert_eq!(
+        state.get_block(&rejected[0].id()).await.unwrap(),
+        rejected[0]
+    );
+
+    state.write_block(&rejected[1]).await.unwrap();
+    assert_eq!(state.compact_rejected().await.unwrap(), 0, "done once");
+}
+
+/// `RUST_LOG=debug cargo test --package timestampvm --lib -- state::test_get_block_cache --exact --show-output`
+#[tokio::test]
+async fn test_get_block_cache() {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let mut state = State::default();
+    let genesis_blk = Block::try_new(
+        ids::Id::empty(),
+        0,
+        0,
+        vec![0],
+        choices::status::Status::Accepted,
+    )
+    .unwrap();
+    state.write_accepted_block(&genesis_blk).await.unwrap();
+
+    // accepting caches the block and moves the last accepted pointer
+    assert_eq!(state.get_last_accepted_block().await.unwrap(), genesis_blk);
+    assert_eq!(
+        state.get_block(&genesis_blk.id()).await.unwrap(),
+        genesis_blk
+    );
+    assert_eq!(state.metrics.snapshot().accepted_cache_hits, 1);
+    assert_eq!(state.metrics.snapshot().accepted_cache_misses, 0);
+
+    // a cold cache reads from the db once
+    state.accepted_cache.write().await.clear();
+    assert_eq!(
+        state.get_last_accepted_block_id().await.unwrap(),
+        genesis_blk.id()
+    );
+    assert_eq!(state.get_last_accepted_block().await.unwrap(), genesis_blk);
+    assert_eq!(
+        state.get_block(&genesis_blk.id()).await.unwrap(),
+        genesis_blk
+    );
+    let snapshot = state.metrics.snapshot();
+    assert_eq!(snapshot.accepted_cache_hits, 2);
+    assert_eq!(snapshot.accepted_cache_misses, 1);
+
+    // blocks that are not accepted are never cached
+    let mut blk1 = Block::try_new(
+        genesis_blk.id(),
+        1,
+        1,
+        vec![1],
+        choices::status::Status::Processing,
+    )
+    .unwrap();
+    state.write_block(&blk1).await.unwrap();
+    state.get_block(&blk1.id()).await.unwrap();
+    state.get_block(&blk1.id()).await.unwrap();
+    assert_eq!(state.metrics.snapshot().accepted_cache_misses, 3);
+
+    blk1.set_status(choices::status::Status::Accepted);
+    state.write_accepted_block(&blk1).await.unwrap();
+    assert_eq!(state.get_last_accepted_block().await.unwrap(), blk1);
+    assert_eq!(
+        state.get_block(&blk1.id()).await.unwrap().status(),
+        choices::status::Status::Accepted
+    );
+
+    // rejecting drops the cached block but keeps the last accepted pointer
+    state.add_rejected(&genesis_blk).await;
+    assert_eq!(state.accepted_cache.read().await.len(), 1);
+    state.add_rejected(&blk1).await;
+    assert_eq!(
+        state.accepted_cache.read().await.last_accepted(),
+        Some(&blk1)
+    );
+
+    // rewriting drops the cached block
+    state.write_block(&blk1).await.unwrap();
+    assert!(state.accepted_cache.read().await.last_accepted().is_none());
+    state.write_block(&genesis_blk).await.unwrap();
+    assert!(state.accepted_cache.read().await.is_empty());
 }
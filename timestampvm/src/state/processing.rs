@@ -0,0 +1,176 @@
+//! Tracks the verified but undecided blocks as a tree, from parent to children.
+
+use std::collections::{HashMap, HashSet};
+
+use avalanche_types::ids;
+
+use crate::block::Block;
+
+/// Default maximum number of verified but undecided blocks.
+pub const DEFAULT_MAX_PROCESSING_BLOCKS: usize = 1024;
+
+/// Holds the blocks that are verified but not yet accepted or rejected, linked
+/// from parent to children, so that accepting a block can drop every block that
+/// conflicts with it.
+#[derive(Debug, Clone)]
+pub struct ProcessingTree {
+    max_blocks: usize,
+    blocks: HashMap<ids::Id, Block>,
+    /// Maps a block Id, processing or not, to its processing children.
+    children: HashMap<ids::Id, HashSet<ids::Id>>,
+}
+
+impl Default for ProcessingTree {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_PROCESSING_BLOCKS)
+    }
+}
+
+impl ProcessingTree {
+    #[must_use]
+    pub fn new(max_blocks: usize) -> Self {
+        Self {
+            max_blocks,
+            blocks: HashMap::new(),
+            children: HashMap::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    #[must_use]
+    pub fn get(&self, blk_id: &ids::Id) -> Option<&Block> {
+        self.blocks.get(blk_id)
+    }
+
+    #[must_use]
+    pub fn contains_key(&self, blk_id: &ids::Id) -> bool {
+        self.blocks.contains_key(blk_id)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Block> {
+        self.blocks.values()
+    }
+
+    /// Returns the processing children of the block.
+    #[must_use]
+    pub fn children(&self, blk_id: &ids::Id) -> Vec<ids::Id> {
+        self.children
+            .get(blk_id)
+            .map(|c| c.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Adds the block under its parent.
+    /// Returns "false" without adding it if the tree is full.
+    pub fn insert(&mut self, block: Block) -> bool {
+        let blk_id = block.id();
+        if !self.blocks.contains_key(&blk_id) && self.blocks.len() >= self.max_blocks {
+            return false;
+        }
+        self.children
+            .entry(block.parent_id())
+            .or_default()
+            .insert(blk_id);
+        self.blocks.insert(blk_id, block);
+        true
+    }
+
+    /// Removes the block, keeping its children.
+    pub fn remove(&mut self, blk_id: &ids::Id) -> Option<Block> {
+        let block = self.blocks.remove(blk_id)?;
+        if let Some(siblings) = self.children.get_mut(&block.parent_id()) {
+            siblings.remove(blk_id);
+            if siblings.is_empty() {
+                self.children.remove(&block.parent_id());
+            }
+        }
+        Some(block)
+    }
+
+    /// Removes the accepted block and every block that does not descend from it,
+    /// i.e., its siblings, their descendants, and any block left from earlier
+    /// decisions, since none of them can be accepted anymore.
+    /// Returns the removed blocks other than the accepted one.
+    pub fn prune(&mut self, accepted: &ids::Id) -> Vec<Block> {
+        let mut keep = HashSet::new();
+        let mut next = self.children(accepted);
+        while let Some(blk_id) = next.pop() {
+            next.extend(self.children(&blk_id));
+            keep.insert(blk_id);
+        }
+
+        self.remove(accepted);
+        let pruned: Vec<ids::Id> = self
+            .blocks
+            .keys()
+            .filter(|blk_id| !keep.contains(*blk_id))
+            .copied()
+            .collect();
+        pruned
+            .iter()
+            .filter_map(|blk_id| self.remove(blk_id))
+            .collect()
+    }
+}
+
+/// `RUST_LOG=debug cargo test --package timestampvm --lib -- state::processing::test_processing_tree --exact --show-output`
+#[test]
+fn test_processing_tree() {
+    use avalanche_types::choices::status::Status;
+
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let new_block = |parent_id: ids::Id, height: u64, data: u8| {
+        Block::try_new(parent_id, height, 0, vec![data], Status::Processing).unwrap()
+    };
+
+    // genesis <- a <- a1
+    //         <- b <- b1 <- b2
+    let genesis = ids::Id::sha256(b"genesis");
+    let a = new_block(genesis, 1, 0);
+    let a1 = new_block(a.id(), 2, 1);
+    let b = new_block(genesis, 1, 2);
+    let b1 = new_block(b.id(), 2, 3);
+    let b2 = new_block(b1.id(), 3, 4);
+
+    let mut tree = ProcessingTree::new(5);
+    for blk in [&a, &a1, &b, &b1, &b2] {
+        assert!(tree.insert(blk.clone()));
+    }
+    assert_eq!(tree.len(), 5);
+    assert!(tree.insert(b2.clone()), "re-adding is not limited");
+    assert!(!tree.insert(new_block(genesis, 1, 5)), "full");
+
+    let mut children = tree.children(&genesis);
+    children.sort_by(|x, y| x.as_ref().cmp(y.as_ref()));
+    let mut expected = vec![a.id(), b.id()];
+    expected.sort_by(|x, y| x.as_ref().cmp(y.as_ref()));
+    assert_eq!(children, expected);
+
+    // accepting b drops a and a1, and keeps the descendants of b
+    let mut pruned: Vec<ids::Id> = tree.prune(&b.id()).iter().map(Block::id).collect();
+    pruned.sort_by(|x, y| x.as_ref().cmp(y.as_ref()));
+    let mut expected = vec![a.id(), a1.id()];
+    expected.sort_by(|x, y| x.as_ref().cmp(y.as_ref()));
+    assert_eq!(pruned, expected);
+    assert_eq!(tree.len(), 2);
+    assert!(tree.contains_key(&b1.id()) && tree.contains_key(&b2.id()));
+    assert!(tree.children(&genesis).is_empty());
+
+    assert!(tree.prune(&b1.id()).is_empty());
+    assert_eq!(tree.remove(&b2.id()).unwrap(), b2);
+    assert!(tree.is_empty());
+    assert!(tree.children.is_empty());
+}
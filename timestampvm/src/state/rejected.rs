@@ -0,0 +1,135 @@
+//! Keeps recently rejected blocks in memory, since they are not persisted.
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use avalanche_types::ids;
+
+use crate::block::Block;
+
+/// Default maximum number of recently rejected blocks kept in memory.
+pub const DEFAULT_MAX_REJECTED_BLOCKS: usize = 256;
+/// Default time a rejected block is kept in memory, in seconds.
+pub const DEFAULT_REJECTED_BLOCK_TTL_SECS: u64 = 600;
+
+/// Holds the most recently rejected blocks, up to a count and for a limited time,
+/// so that peers and the engine can still look them up shortly after the decision
+/// without letting conflicting blocks grow the database.
+#[derive(Debug, Clone)]
+pub struct RecentlyRejected {
+    max_blocks: usize,
+    ttl: Duration,
+    /// Rejected blocks with their rejection time, oldest first.
+    blocks: VecDeque<(Instant, Block)>,
+}
+
+impl Default for RecentlyRejected {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_MAX_REJECTED_BLOCKS,
+            Duration::from_secs(DEFAULT_REJECTED_BLOCK_TTL_SECS),
+        )
+    }
+}
+
+impl RecentlyRejected {
+    #[must_use]
+    pub fn new(max_blocks: usize, ttl: Duration) -> Self {
+        Self {
+            max_blocks,
+            ttl,
+            blocks: VecDeque::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Adds the rejected block, evicting the oldest ones that are expired
+    /// or over the limit.
+    pub fn insert(&mut self, block: Block, now: Instant) {
+        self.evict_expired(now);
+        if self.max_blocks == 0 {
+            return;
+        }
+        while self.blocks.len() >= self.max_blocks {
+            self.blocks.pop_front();
+        }
+        self.blocks.push_back((now, block));
+    }
+
+    /// Returns the rejected block if it is still kept.
+    #[must_use]
+    pub fn get(&self, blk_id: &ids::Id, now: Instant) -> Option<&Block> {
+        self.blocks
+            .iter()
+            .rev()
+            .find(|(rejected_at, blk)| blk.id() == *blk_id && !self.is_expired(*rejected_at, now))
+            .map(|(_, blk)| blk)
+    }
+
+    /// Drops the blocks kept for longer than the time limit.
+    pub fn evict_expired(&mut self, now: Instant) {
+        while let Some((rejected_at, _)) = self.blocks.front() {
+            if !self.is_expired(*rejected_at, now) {
+                break;
+            }
+            self.blocks.pop_front();
+        }
+    }
+
+    fn is_expired(&self, rejected_at: Instant, now: Instant) -> bool {
+        now.saturating_duration_since(rejected_at) >= self.ttl
+    }
+}
+
+/// `RUST_LOG=debug cargo test --package timestampvm --lib -- state::rejected::test_recently_rejected --exact --show-output`
+#[test]
+fn test_recently_rejected() {
+    use avalanche_types::choices::status::Status;
+
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let blks: Vec<Block> = (0..3_u8)
+        .map(|i| Block::try_new(ids::Id::empty(), 1, 0, vec![i], Status::Rejected).unwrap())
+        .collect();
+    let start = Instant::now();
+    let ttl = Duration::from_secs(10);
+
+    // bounded by count, oldest first
+    let mut rejected = RecentlyRejected::new(2, ttl);
+    for (i, blk) in (0_u64..).zip(blks.iter()) {
+        rejected.insert(blk.clone(), start + Duration::from_secs(i));
+    }
+    assert_eq!(rejected.len(), 2);
+    let now = start + Duration::from_secs(2);
+    assert!(rejected.get(&blks[0].id(), now).is_none());
+    assert_eq!(rejected.get(&blks[1].id(), now), Some(&blks[1]));
+    assert_eq!(rejected.get(&blks[2].id(), now), Some(&blks[2]));
+
+    // bounded by time
+    let now = start + Duration::from_secs(11);
+    assert!(rejected.get(&blks[1].id(), now).is_none());
+    assert!(rejected.get(&blks[2].id(), now).is_some());
+    rejected.evict_expired(now);
+    assert_eq!(rejected.len(), 1);
+    rejected.evict_expired(start + Duration::from_secs(12));
+    assert!(rejected.is_empty());
+
+    // nothing kept at all
+    let mut rejected = RecentlyRejected::new(0, ttl);
+    rejected.insert(blks[0].clone(), start);
+    assert!(rejected.is_empty());
+}
@@ -0,0 +1,382 @@
+//! Defines the network upgrade schedule, passed as the upgrade bytes to
+//! `initialize` (i.e., the upgrade file of `AvalancheGo`).
+//!
+//! Each upgrade activates at a unix timestamp and overrides some of the
+//! consensus [`Rules`](Rules). A block is verified and built with the rules
+//! active at its parent's timestamp, so that every node switches at the same block.
+
+use std::{
+    collections::HashSet,
+    fmt,
+    io::{self, Error, ErrorKind},
+};
+
+use crate::{block, config::VmConfig};
+use avalanche_types::ids;
+use serde::{Deserialize, Serialize};
+
+/// Represents the consensus rules blocks are built and verified with.
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+pub struct Rules {
+    /// Limits how much data a single proposal can carry.
+    pub propose_limit_bytes: usize,
+    /// Limits the encoded size of a block.
+    pub block_size_limit: usize,
+    /// Limits how far ahead of the local time a block timestamp can be, in seconds.
+    pub max_future_block_time_secs: u64,
+    /// Set "true" to build blocks with the binary codec, packing multiple proposals,
+    /// and to reject the single-proposal JSON blocks of the earlier versions.
+    /// Off until an upgrade (or the genesis of a new chain) turns it on, since
+    /// nodes without the binary codec can't read binary blocks.
+    #[serde(default)]
+    pub binary_blocks: bool,
+    /// Addresses of the keys allowed to sign proposals, see
+    /// [`ProposalSignature`](crate::block::signature::ProposalSignature).
+    /// Empty allows anyone, including unsigned proposals.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_proposers: Vec<ids::short::Id>,
+}
+
+impl From<&VmConfig> for Rules {
+    fn from(config: &VmConfig) -> Self {
+        Self {
+            propose_limit_bytes: config.propose_limit_bytes,
+            block_size_limit: config.block_size_limit,
+            max_future_block_time_secs: config.max_future_block_time_secs,
+            // set by the genesis or upgrades only, as every node must agree on them
+            binary_blocks: false,
+            allowed_proposers: Vec::new(),
+        }
+    }
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self::from(&VmConfig::default())
+    }
+}
+
+impl Rules {
+    /// Ensures a proposal at the limit fits in a block.
+    /// # Errors
+    /// Fails if the proposal limit is zero, a proposal at the limit can't fit in a block,
+    /// or proposers are restricted before binary blocks, which alone carry signatures.
+    pub fn validate(&self) -> io::Result<()> {
+        if self.propose_limit_bytes == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "propose_limit_bytes must be positive",
+            ));
+        }
+        if !self.allowed_proposers.is_empty() && !self.binary_blocks {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "allowed_proposers requires binary_blocks",
+            ));
+        }
+
+        let max_block_size = if self.binary_blocks {
+            block::ENCODED_HEADER_LEN
+                + block::Entry::new(vec![0; self.propose_limit_bytes]).encoded_len()
+        } else {
+            // the largest Id, height and timestamp have the longest JSON encoding
+            block::Block::try_new(
+                ids::Id::from_slice(&[0xff; ids::LEN]),
+                u64::MAX,
+                u64::MAX,
+                vec![0; self.propose_limit_bytes],
+                avalanche_types::choices::status::Status::default(),
+            )?
+            .bytes()
+            .len()
+        };
+        if self.block_size_limit < max_block_size {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "block_size_limit {} can't fit a {}-byte proposal (needs {max_block_size})",
+                    self.block_size_limit, self.propose_limit_bytes
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks that the entry was signed by an allowed proposer, if the rules restrict proposers.
+    /// The signature itself is checked by [`Entry::verify_signature`](block::Entry::verify_signature).
+    /// # Errors
+    /// Fails with `InvalidData` if the entry is unsigned or its proposer is not allowed.
+    pub fn check_proposer(&self, entry: &block::Entry) -> io::Result<()> {
+        if self.allowed_proposers.is_empty() {
+            return Ok(());
+        }
+        match entry.proposer() {
+            Some(proposer) if self.allowed_proposers.contains(&proposer) => Ok(()),
+            Some(proposer) => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("proposer {proposer} is not allowed"),
+            )),
+            None => Err(Error::new(
+                ErrorKind::InvalidData,
+                "unsigned proposals are not allowed",
+            )),
+        }
+    }
+
+    /// Overrides the rules that are set in `overrides`.
+    pub fn apply(&mut self, overrides: &RuleOverrides) {
+        if let Some(v) = overrides.propose_limit_bytes {
+            self.propose_limit_bytes = v;
+        }
+        if let Some(v) = overrides.block_size_limit {
+            self.block_size_limit = v;
+        }
+        if let Some(v) = overrides.max_future_block_time_secs {
+            self.max_future_block_time_secs = v;
+        }
+        if let Some(v) = overrides.binary_blocks {
+            self.binary_blocks = v;
+        }
+        if let Some(v) = &overrides.allowed_proposers {
+            self.allowed_proposers.clone_from(v);
+        }
+    }
+}
+
+/// Represents the rules an upgrade changes. Missing fields keep their previous values.
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuleOverrides {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub propose_limit_bytes: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_size_limit: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_future_block_time_secs: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub binary_blocks: Option<bool>,
+    /// Replaces the allowed proposers. Set to an empty list to allow anyone again.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allowed_proposers: Option<Vec<ids::short::Id>>,
+}
+
+impl RuleOverrides {
+    /// Returns "true" if no rule is overridden.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Represents a named upgrade.
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Upgrade {
+    pub name: String,
+    /// Unix second from which blocks whose parent is at or after it follow the new rules.
+    pub activation_timestamp: u64,
+    #[serde(default)]
+    pub rules: RuleOverrides,
+}
+
+/// Represents the upgrade bytes.
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct UpgradeConfig {
+    /// Upgrades in activation order.
+    #[serde(default)]
+    pub upgrades: Vec<Upgrade>,
+}
+
+impl UpgradeConfig {
+    /// Encodes the upgrade config to JSON bytes.
+    /// # Errors
+    /// Fails if `Self` can't be serialized
+    pub fn to_vec(&self) -> io::Result<Vec<u8>> {
+        serde_json::to_vec(&self).map_err(|e| {
+            Error::new(
+                ErrorKind::Other,
+                format!("failed to serialize UpgradeConfig to JSON bytes {e}"),
+            )
+        })
+    }
+
+    /// Decodes the upgrade config from JSON bytes.
+    /// Empty bytes (i.e., no upgrade file) yield no upgrades.
+    /// # Errors
+    /// Fails if the bytes can't be deserialized
+    pub fn from_slice<S>(d: S) -> io::Result<Self>
+    where
+        S: AsRef<[u8]>,
+    {
+        let d = d.as_ref();
+        if d.iter().all(u8::is_ascii_whitespace) {
+            return Ok(Self::default());
+        }
+
+        serde_json::from_slice(d).map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("failed to decode UpgradeConfig {e}"),
+            )
+        })
+    }
+}
+
+/// Resolves the rules active at a given parent block timestamp.
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone, Default)]
+pub struct Schedule {
+    /// Rules before any upgrade activates.
+    pub base: Rules,
+    /// Upgrades in strictly increasing activation order.
+    pub upgrades: Vec<Upgrade>,
+}
+
+impl Schedule {
+    /// Creates a schedule and checks that the rules stay valid after each upgrade.
+    /// # Errors
+    /// Fails if upgrade names are empty or repeated, if activations are not
+    /// strictly increasing, or if any resulting rule set is invalid.
+    pub fn try_new(base: Rules, upgrades: Vec<Upgrade>) -> io::Result<Self> {
+        base.validate()?;
+
+        let mut names = HashSet::new();
+        let mut rules = base.clone();
+        for (i, upgrade) in upgrades.iter().enumerate() {
+            if upgrade.name.is_empty() || !names.insert(upgrade.name.as_str()) {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("upgrade name '{}' is empty or repeated", upgrade.name),
+                ));
+            }
+            if i > 0 && upgrade.activation_timestamp <= upgrades[i - 1].activation_timestamp {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "upgrade '{}' must activate after '{}'",
+                        upgrade.name,
+                        upgrades[i - 1].name
+                    ),
+                ));
+            }
+
+            rules.apply(&upgrade.rules);
+            rules.validate().map_err(|e| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("invalid rules after upgrade '{}': {e}", upgrade.name),
+                )
+            })?;
+        }
+
+        Ok(Self { base, upgrades })
+    }
+
+    /// Returns the rules for a block whose parent has the given timestamp.
+    #[must_use]
+    pub fn rules_at(&self, parent_timestamp: u64) -> Rules {
+        let mut rules = self.base.clone();
+        for upgrade in self.active_at(parent_timestamp) {
+            rules.apply(&upgrade.rules);
+        }
+        rules
+    }
+
+    /// Returns the largest block size limit any block of the chain can have.
+    #[must_use]
+    pub fn max_block_size_limit(&self) -> usize {
+        let mut rules = self.base.clone();
+        let mut limit = rules.block_size_limit;
+        for upgrade in &self.upgrades {
+            rules.apply(&upgrade.rules);
+            limit = limit.max(rules.block_size_limit);
+        }
+        limit
+    }
+
+    /// Returns the upgrades active for a block whose parent has the given timestamp.
+    pub fn active_at(&self, parent_timestamp: u64) -> impl Iterator<Item = &Upgrade> {
+        self.upgrades
+            .iter()
+            .take_while(move |u| u.activation_timestamp <= parent_timestamp)
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = serde_json::to_string(&self).unwrap();
+        write!(f, "{s}")
+    }
+}
+
+/// `RUST_LOG=debug cargo test --package timestampvm --lib -- upgrade::test_schedule --exact --show-output`
+#[test]
+fn test_schedule() {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    assert_eq!(
+        UpgradeConfig::from_slice([]).unwrap(),
+        UpgradeConfig::default()
+    );
+
+    let upgrade_config = UpgradeConfig::from_slice(
+        r#"{"upgrades":[
+            {"name":"small","activation_timestamp":100,"rules":{"propose_limit_bytes":10}},
+            {"name":"binary_only","activation_timestamp":200,"rules":{"binary_blocks":true}},
+            {"name":"permissioned","activation_timestamp":300,"rules":{"allowed_proposers":["6Y3kysjF9jnHnYkdS9yGAuoHyae2eNmeV"]}},
+            {"name":"open","activation_timestamp":400,"rules":{"allowed_proposers":[]}}
+        ]}"#,
+    )
+    .unwrap();
+    assert_eq!(
+        UpgradeConfig::from_slice(upgrade_config.to_vec().unwrap()).unwrap(),
+        upgrade_config
+    );
+
+    let base = Rules::default();
+    let schedule = Schedule::try_new(base.clone(), upgrade_config.upgrades.clone()).unwrap();
+    assert_eq!(schedule.rules_at(99), base);
+    assert_eq!(schedule.rules_at(100).propose_limit_bytes, 10);
+    assert!(!schedule.rules_at(199).binary_blocks);
+    let rules = schedule.rules_at(200);
+    assert_eq!(rules.propose_limit_bytes, 10);
+    assert!(rules.binary_blocks);
+    assert_eq!(schedule.active_at(150).count(), 1);
+    assert_eq!(schedule.max_block_size_limit(), base.block_size_limit);
+
+    let proposer: ids::short::Id = "6Y3kysjF9jnHnYkdS9yGAuoHyae2eNmeV".parse().unwrap();
+    let unsigned = block::Entry::new(vec![1]);
+    assert!(schedule.rules_at(299).check_proposer(&unsigned).is_ok());
+    let rules = schedule.rules_at(300);
+    assert_eq!(rules.allowed_proposers, vec![proposer]);
+    assert!(rules
+        .check_proposer(&unsigned)
+        .unwrap_err()
+        .to_string()
+        .contains("unsigned proposals are not allowed"));
+    assert!(schedule.rules_at(400).check_proposer(&unsigned).is_ok());
+
+    // out of order, repeated names, and rules that can't fit a proposal
+    let mut reordered = upgrade_config.upgrades.clone();
+    reordered.reverse();
+    assert!(Schedule::try_new(base.clone(), reordered).is_err());
+    let mut repeated = upgrade_config.upgrades.clone();
+    repeated[1].name = String::from("small");
+    assert!(Schedule::try_new(base.clone(), repeated).is_err());
+    let mut too_small = upgrade_config.upgrades.clone();
+    too_small[1].rules.block_size_limit = Some(10);
+    assert!(Schedule::try_new(base.clone(), too_small).is_err());
+
+    // signatures only fit in binary blocks
+    let mut json_permissioned = upgrade_config.upgrades.clone();
+    json_permissioned[1].rules.binary_blocks = None;
+    assert!(Schedule::try_new(base, json_permissioned)
+        .unwrap_err()
+        .to_string()
+        .contains("requires binary_blocks"));
+
+    assert!(UpgradeConfig::from_slice(r#"{"upgrades":[{"name":"x"}]}"#).is_err());
+}
@@ -1,20 +1,26 @@
 //! Implementation of [`snowman.block.ChainVM`](https://pkg.go.dev/github.com/ava-labs/avalanchego/snow/engine/snowman/block#ChainVM) interface for timestampvm.
 
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::HashMap,
     io::{self, Error, ErrorKind},
     sync::Arc,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use crate::{
     api::{
+        admin_handlers::AdminService,
         chain_handlers::{ChainHandler, ChainService},
         static_handlers::{StaticHandler, StaticService},
     },
-    block::Block,
+    block::{self, signature::ProposalSignature, Block},
+    config::{self, VmConfig},
     genesis::Genesis,
-    state,
+    gossip,
+    mempool::{self, Mempool},
+    metrics::Metrics,
+    state::{self, cache::AcceptedCache, processing::ProcessingTree, rejected::RecentlyRejected},
+    upgrade::{self, Rules, UpgradeConfig},
 };
 use avalanche_types::{
     choices, ids,
@@ -41,12 +47,160 @@ use avalanche_types::{
 use bytes::Bytes;
 use chrono::{DateTime, Utc};
 use semver::Version;
+use serde::{Deserialize, Serialize};
 use tokio::sync::{mpsc::Sender, RwLock};
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
-/// Limits how much data a user can propose.
-pub const PROPOSE_LIMIT_BYTES: usize = 1024 * 1024;
+/// Limits how much data a user can propose, unless overridden by
+/// [`VmConfig::propose_limit_bytes`](crate::config::VmConfig::propose_limit_bytes).
+pub const PROPOSE_LIMIT_BYTES: usize = config::DEFAULT_PROPOSE_LIMIT_BYTES;
+
+/// Checks a proposal against the proposal limits and the allowed proposers,
+/// and checks its signature for the chain if signed.
+/// Applies to both local `proposeBlock` calls and gossiped proposals.
+fn check_proposal(entry: &block::Entry, rules: &Rules, chain_id: &ids::Id) -> io::Result<()> {
+    let propose_limit_bytes = rules.propose_limit_bytes;
+    let size = entry.data().len();
+    if size > propose_limit_bytes {
+        log::info!("limit exceeded... returning an error...");
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("data {size}-byte exceeds the limit {propose_limit_bytes}-byte"),
+        ));
+    }
+    entry
+        .verify_signature(chain_id)
+        .and_then(|()| rules.check_proposer(entry))
+        .map_err(|e| Error::new(ErrorKind::InvalidInput, e.to_string()))
+}
+
+/// Takes the entries of the next block from the front of the mempool:
+/// as many as fit under the block size limit, or a single one while blocks
+/// keep the JSON encoding. Drops the entries that the rules no longer admit
+/// or that a verified or accepted block already carries.
+/// # Errors
+/// Fails if the blocks can't be encoded or the db can't be read
+async fn take_entries(
+    mempool: &mut mempool::Mempool,
+    state: &state::State,
+    prnt_blk: &Block,
+    unix_now: u64,
+    rules: &Rules,
+) -> io::Result<Vec<(ids::Id, block::Entry)>> {
+    let mut size = block::ENCODED_HEADER_LEN;
+    let max_entries = if rules.binary_blocks { usize::MAX } else { 1 };
+    let mut entries = Vec::new();
+    while let Some((id, entry)) = mempool.front() {
+        if entry.data().len() > rules.propose_limit_bytes {
+            log::warn!("dropping {id} that exceeds the active proposal limit");
+            mempool.pop_front();
+            continue;
+        }
+        // an upgrade may have changed the allowed proposers since admission
+        if let Err(e) = rules.check_proposer(entry) {
+            log::warn!("dropping {id}: {e}");
+            mempool.pop_front();
+            continue;
+        }
+        // e.g., a block of another node took the same gossiped proposal
+        let included = match state.find_proposal_block_id(id).await {
+            Ok(included) => included,
+            Err(e) => {
+                mempool.restore_front(entries);
+                return Err(e);
+            }
+        };
+        if let Some(blk_id) = included {
+            log::info!("dropping {id} already in block {blk_id}");
+            mempool.pop_front();
+            continue;
+        }
+
+        if entries.len() == max_entries {
+            break;
+        }
+
+        let block_size = if rules.binary_blocks {
+            size + entry.encoded_len()
+        } else {
+            Block::try_new(
+                prnt_blk.id(),
+                prnt_blk.height() + 1,
+                unix_now,
+                entry.data().to_vec(),
+                choices::status::Status::Processing,
+            )?
+            .bytes()
+            .len()
+        };
+        if block_size > rules.block_size_limit {
+            if !entries.is_empty() {
+                break;
+            }
+            log::warn!("dropping {id} that can't fit in any block");
+            mempool.pop_front();
+            continue;
+        }
+
+        size = block_size;
+        if let Some(taken) = mempool.pop_front() {
+            entries.push(taken);
+        }
+    }
+    Ok(entries)
+}
+
+/// Builds the child block of the parent that carries the entries,
+/// in the encoding of the given rules, and verifies it.
+async fn build_verified_block(
+    state: &state::State,
+    prnt_blk: &Block,
+    unix_now: u64,
+    rules: &Rules,
+    entries: &[(ids::Id, block::Entry)],
+) -> io::Result<Block> {
+    let mut block = if rules.binary_blocks {
+        Block::try_new_with_entries(
+            prnt_blk.id(),
+            prnt_blk.height() + 1,
+            unix_now,
+            entries.iter().map(|(_, entry)| entry.clone()).collect(),
+            choices::status::Status::Processing,
+        )?
+    } else {
+        Block::try_new(
+            prnt_blk.id(),
+            prnt_blk.height() + 1,
+            unix_now,
+            entries[0].1.data().to_vec(),
+            choices::status::Status::Processing,
+        )?
+    };
+    block.set_state(state.clone());
+    block.verify().await?;
+    Ok(block)
+}
+
+/// Size of the length prefix the engine adds to each container in an ancestors response.
+const ANCESTOR_LEN_PREFIX_BYTES: usize = 4;
+
+/// Represents where a proposal is, as seen by this node.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum ProposalStatus {
+    /// Waiting in the mempool.
+    Pending,
+    /// Included in a verified block that is not decided yet.
+    #[serde(rename_all = "camelCase")]
+    Processing { block_id: ids::Id },
+    /// Included in an accepted block.
+    #[serde(rename_all = "camelCase")]
+    Accepted { block_id: ids::Id, height: u64 },
+    /// Neither pending, processing nor accepted: evicted from a full mempool,
+    /// in blocks rejected more than "`max_requeues`" times, or never received by this node.
+    Dropped,
+}
 
 /// Represents VM-specific states.
 /// Defined in a separate struct, for interior mutability in [`Vm`](Vm).
@@ -65,6 +219,9 @@ pub struct State {
     /// Set "true" to indicate that the Vm has finished bootstrapping
     /// for the chain.
     pub bootstrapped: bool,
+
+    /// Config parsed from the chain config bytes.
+    pub config: VmConfig,
 }
 
 impl Default for State {
@@ -78,6 +235,8 @@ impl Default for State {
             preferred: ids::Id::empty(),
             to_engine: None,
             bootstrapped: false,
+
+            config: VmConfig::default(),
         }
     }
 }
@@ -91,7 +250,11 @@ pub struct Vm<A> {
 
     /// A queue of data that have not been put into a block and proposed yet.
     /// Mempool is not persistent, so just keep in memory via Vm.
-    pub mempool: Arc<RwLock<VecDeque<Vec<u8>>>>,
+    pub mempool: Arc<RwLock<Mempool>>,
+
+    /// Maps proposal Id to the number of times it was returned to the mempool
+    /// from rejected blocks.
+    pub requeues: Arc<RwLock<HashMap<ids::Id, u32>>>,
 }
 
 impl<A> Default for Vm<A>
@@ -112,7 +275,8 @@ where
         Self {
             state: Arc::new(RwLock::new(State::default())),
             app_sender: None,
-            mempool: Arc::new(RwLock::new(VecDeque::with_capacity(100))),
+            mempool: Arc::new(RwLock::new(Mempool::default())),
+            requeues: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 
@@ -136,30 +300,6 @@ where
         }
     }
 
-    /// Proposes arbitrary data to mempool and notifies that a block is ready for builds.
-    /// Other VMs may optimize mempool with more complicated batching mechanisms.
-    /// # Errors
-    /// Can fail if the data size exceeds `PROPOSE_LIMIT_BYTES`.
-    pub async fn propose_block(&self, d: Vec<u8>) -> io::Result<()> {
-        let size = d.len();
-        log::info!("received propose_block of {size} bytes");
-
-        if size > PROPOSE_LIMIT_BYTES {
-            log::info!("limit exceeded... returning an error...");
-            return Err(Error::new(
-                ErrorKind::InvalidInput,
-                format!("data {size}-byte exceeds the limit {PROPOSE_LIMIT_BYTES}-byte"),
-            ));
-        }
-
-        let mut mempool = self.mempool.write().await;
-        mempool.push_back(d);
-        log::info!("proposed {size} bytes of data for a block");
-
-        self.notify_block_ready().await;
-        Ok(())
-    }
-
     /// Sets the state of the Vm.
     /// # Errors
     /// Will fail if the `snow::State` is syncing
@@ -194,6 +334,118 @@ where
         }
     }
 
+    /// Decodes a block against the given state, returning the previously
+    /// known block (verified or decided) if the same block was parsed before.
+    async fn parse_block_with_state(state: &state::State, bytes: &[u8]) -> io::Result<Block> {
+        let mut new_block = Block::from_slice(bytes)?;
+        new_block.set_status(choices::status::Status::Processing);
+        new_block.set_state(state.clone());
+        log::debug!("parsed block {}", new_block.id());
+
+        match state.get_block(&new_block.id()).await {
+            Ok(prev) => {
+                log::debug!("returning previously parsed block {}", prev.id());
+                Ok(prev)
+            }
+            Err(_) => Ok(new_block),
+        }
+    }
+
+    /// Returns the rules the next block is built with, i.e., the rules active
+    /// at the preferred block timestamp. Falls back to the configured base rules
+    /// before the state is initialized.
+    /// # Errors
+    /// Will fail if the preferred block can't be read
+    pub async fn current_rules(&self) -> io::Result<Rules> {
+        let vm_state = self.state.read().await;
+        match &vm_state.state {
+            Some(state) => {
+                let prnt_blk = state.get_block(&vm_state.preferred).await?;
+                Ok(state.schedule.rules_at(prnt_blk.timestamp()))
+            }
+            None => Ok(Rules::from(&vm_state.config)),
+        }
+    }
+
+    /// Returns the proposals of rejected blocks that this node built to the mempool,
+    /// unless they are already accepted, processing or pending, and notifies the engine
+    /// if any was returned. Proposals that the current rules no longer admit are dropped.
+    /// A proposal is returned at most "`max_requeues`" times, then dropped.
+    /// Returns the number of proposals returned.
+    /// # Errors
+    /// Will fail if there's no state or if the db can't be accessed
+    pub async fn requeue_rejected(&self) -> io::Result<usize> {
+        // same lock order as "build_block"
+        let mut mempool = self.mempool.write().await;
+        let vm_state = self.state.read().await;
+        let Some(state) = &vm_state.state else {
+            return Err(Error::new(ErrorKind::NotFound, "state manager not found"));
+        };
+        let entries = std::mem::take(&mut *state.rejected_entries.write().await);
+        if entries.is_empty() {
+            return Ok(0);
+        }
+        let max_requeues = vm_state.config.max_requeues;
+        let prnt_blk = state.get_block(&vm_state.preferred).await?;
+        let rules = state.schedule.rules_at(prnt_blk.timestamp());
+
+        let mut requeues = self.requeues.write().await;
+        let mut returned = 0;
+        for entry in entries {
+            let id = ids::Id::sha256(entry.data());
+            // e.g., an upgrade lowered the proposal limit or restricted the proposers
+            if let Err(e) = check_proposal(&entry, &rules, &state.chain_id) {
+                log::warn!("dropping rejected proposal {id}: {e}");
+                requeues.remove(&id);
+                Metrics::inc(&state.metrics.requeue_dropped_proposals);
+                continue;
+            }
+            if state.get_accepted_proposal_block_id(&id).await?.is_some() {
+                requeues.remove(&id);
+                Metrics::inc(&state.metrics.requeue_skipped_proposals);
+                continue;
+            }
+            if mempool.contains(&id) || state.find_verified_proposal_block_id(&id).await.is_some() {
+                Metrics::inc(&state.metrics.requeue_skipped_proposals);
+                continue;
+            }
+
+            let count = requeues.get(&id).copied().unwrap_or_default();
+            if count >= max_requeues {
+                log::warn!("dropping {id} rejected {} time(s)", count + 1);
+                requeues.remove(&id);
+                Metrics::inc(&state.metrics.requeue_dropped_proposals);
+                continue;
+            }
+            match mempool.add(entry) {
+                Ok(_) => {
+                    requeues.insert(id, count + 1);
+                    returned += 1;
+                    Metrics::inc(&state.metrics.requeued_proposals);
+                }
+                Err(e) => {
+                    log::warn!("dropping rejected proposal {id}: {e}");
+                    requeues.remove(&id);
+                    Metrics::inc(&state.metrics.requeue_dropped_proposals);
+                }
+            }
+        }
+
+        // proposals that left the mempool for good, e.g., evicted, are forgotten
+        if requeues.len() > vm_state.config.mempool_max_count {
+            requeues.retain(|id, _| mempool.contains(id));
+        }
+        drop(requeues);
+        drop(mempool);
+        drop(vm_state);
+
+        if returned > 0 {
+            log::info!("returned {returned} proposal(s) of rejected blocks to mempool");
+            self.notify_block_ready().await;
+        }
+        Ok(returned)
+    }
+
     /// Returns the last accepted block Id.
     /// # Errors
     /// Will fail if there's no state or if the db can't be accessed
@@ -205,6 +457,150 @@ where
             None => Err(Error::new(ErrorKind::NotFound, "state manager not found")),
         }
     }
+
+    /// Looks up the proposal with the given Id (sha256 of the proposed data).
+    /// Checks the mempool, then verified blocks, then accepted blocks, which is
+    /// the order a proposal moves in, so that it is never missed in between.
+    /// # Errors
+    /// Will fail if there's no state or if the db can't be accessed
+    pub async fn get_proposal_status(&self, proposal_id: &ids::Id) -> io::Result<ProposalStatus> {
+        if self.mempool.read().await.contains(proposal_id) {
+            return Ok(ProposalStatus::Pending);
+        }
+
+        let vm_state = self.state.read().await;
+        let Some(state) = &vm_state.state else {
+            return Err(Error::new(ErrorKind::NotFound, "state manager not found"));
+        };
+
+        if let Some(block_id) = state.find_verified_proposal_block_id(proposal_id).await {
+            return Ok(ProposalStatus::Processing { block_id });
+        }
+        if let Some(block_id) = state.get_accepted_proposal_block_id(proposal_id).await? {
+            let blk = state.get_block(&block_id).await?;
+            return Ok(ProposalStatus::Accepted {
+                block_id,
+                height: blk.height(),
+            });
+        }
+        Ok(ProposalStatus::Dropped)
+    }
+}
+
+impl<A> Vm<A>
+where
+    A: AppSender + Send + Sync + Clone + 'static,
+{
+    /// Proposes arbitrary data to mempool, gossips it to peers,
+    /// and notifies that a block is ready for builds.
+    /// Returns the proposal Id (sha256 of the data) to query its status with.
+    /// If signed, the proposer address is recorded with the data in the block.
+    /// Other VMs may optimize mempool with more complicated batching mechanisms.
+    /// # Errors
+    /// Can fail if the data size exceeds the active proposal limit, if the signature
+    /// is invalid or its proposer is not allowed, if the data is already pending,
+    /// processing or accepted, or if the mempool is full.
+    pub async fn propose_block(
+        &self,
+        d: Vec<u8>,
+        signature: Option<ProposalSignature>,
+    ) -> io::Result<ids::Id> {
+        let size = d.len();
+        log::info!("received propose_block of {size} bytes");
+
+        let entry = match signature {
+            Some(sig) => block::Entry::new_signed(d, sig),
+            None => block::Entry::new(d),
+        };
+        self.check_new_proposal(&entry).await?;
+
+        let mut mempool = self.mempool.write().await;
+        let id = mempool.add(entry.clone())?;
+        log::info!("proposed {size} bytes of data {id} for a block");
+        drop(mempool);
+
+        self.gossip_proposal(entry).await;
+
+        self.notify_block_ready().await;
+        Ok(id)
+    }
+
+    /// Checks a proposal before it's admitted to the mempool, against the
+    /// current rules and this chain, and ensures no verified or accepted block
+    /// carries it already, so that a signed proposal can't be replayed.
+    /// # Errors
+    /// Fails with `AlreadyExists` if a block carries the proposal, or if any check fails.
+    async fn check_new_proposal(&self, entry: &block::Entry) -> io::Result<()> {
+        let rules = self.current_rules().await?;
+        let vm_state = self.state.read().await;
+        let chain_id = vm_state
+            .state
+            .as_ref()
+            .map_or_else(ids::Id::empty, |state| state.chain_id);
+        check_proposal(entry, &rules, &chain_id)?;
+
+        if let Some(state) = &vm_state.state {
+            let id = ids::Id::sha256(entry.data());
+            if let Some(block_id) = state.find_proposal_block_id(&id).await? {
+                return Err(Error::new(
+                    ErrorKind::AlreadyExists,
+                    format!("proposal {id} is already in block {block_id}"),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends a locally submitted proposal to peers.
+    /// Failures are only logged, since the proposal is already in the local mempool.
+    async fn gossip_proposal(&self, entry: block::Entry) {
+        let Some(app_sender) = &self.app_sender else {
+            log::warn!("app sender not found, skipping proposal gossip");
+            return;
+        };
+
+        let msg = match gossip::Message::from(entry).to_vec() {
+            Ok(msg) => msg,
+            Err(e) => {
+                log::warn!("failed to encode gossip message: {e}");
+                return;
+            }
+        };
+        app_sender
+            .send_app_gossip(msg)
+            .await
+            .unwrap_or_else(|e| log::warn!("failed to gossip proposal: {e}"));
+    }
+
+    /// Adds a proposal gossiped by a peer to the mempool, unless it's
+    /// already pending, processing or accepted. Gossiped proposals are not gossiped further.
+    /// # Errors
+    /// Fails if the message can't be decoded, the data exceeds the active proposal limit,
+    /// or the signature is invalid or its proposer is not allowed.
+    pub async fn receive_gossip(&self, node_id: &ids::node::Id, msg: &[u8]) -> io::Result<()> {
+        let entry = block::Entry::from(gossip::Message::from_slice(msg)?);
+        match self.check_new_proposal(&entry).await {
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                log::debug!("dropping gossiped proposal from {node_id}: {e}");
+                return Ok(());
+            }
+            res => res?,
+        }
+
+        let mut mempool = self.mempool.write().await;
+        match mempool.add(entry) {
+            Ok(id) => log::info!("added gossiped proposal {id} from {node_id} to mempool"),
+            Err(mempool::Error::Duplicate) => {
+                log::debug!("dropping duplicate gossiped proposal from {node_id}");
+                return Ok(());
+            }
+            Err(e) => return Err(e.into()),
+        }
+        drop(mempool);
+
+        self.notify_block_ready().await;
+        Ok(())
+    }
 }
 
 #[tonic::async_trait]
@@ -223,8 +619,8 @@ where
         ctx: Option<Context<Self::ValidatorState>>,
         db_manager: BoxedDatabase,
         genesis_bytes: &[u8],
-        _upgrade_bytes: &[u8],
-        _config_bytes: &[u8],
+        upgrade_bytes: &[u8],
+        config_bytes: &[u8],
         to_engine: Sender<snow::engine::common::message::Message>,
         _fxs: &[snow::engine::common::vm::Fx],
         app_sender: Self::AppSender,
@@ -241,9 +637,32 @@ where
         let genesis = Genesis::from_slice(genesis_bytes)?;
         vm_state.genesis = genesis;
 
-        let state = state::State {
+        let config = VmConfig::from_slice(config_bytes)?;
+        log::info!("initializing Vm with config {config}");
+        vm_state.config = config.clone();
+
+        let mut base_rules = Rules::from(&config);
+        base_rules.apply(&vm_state.genesis.params);
+        let upgrade_config = UpgradeConfig::from_slice(upgrade_bytes)?;
+        let schedule = upgrade::Schedule::try_new(base_rules, upgrade_config.upgrades)?;
+        log::info!("initializing Vm with upgrade schedule {schedule}");
+
+        let mut state = state::State {
             db: Arc::new(RwLock::new(db_manager)),
-            verified_blocks: Arc::new(RwLock::new(HashMap::new())),
+            verified_blocks: Arc::new(RwLock::new(ProcessingTree::new(
+                config.max_processing_blocks,
+            ))),
+            schedule,
+            chain_id: vm_state
+                .ctx
+                .as_ref()
+                .map_or_else(ids::Id::empty, |ctx| ctx.chain_id),
+            accepted_cache: Arc::new(RwLock::new(AcceptedCache::new(config.accepted_cache_size))),
+            recently_rejected: Arc::new(RwLock::new(RecentlyRejected::new(
+                config.max_rejected_blocks,
+                Duration::from_secs(config.rejected_block_ttl_secs),
+            ))),
+            ..Default::default()
         };
         vm_state.state = Some(state.clone());
 
@@ -252,18 +671,47 @@ where
         self.app_sender = Some(app_sender);
 
         let has_last_accepted = state.has_last_accepted_block().await?;
+
+        // databases created before the height index existed need a backfill
+        state.repair_height_index().await?;
+
+        // a restart with different genesis bytes would silently continue the old chain
+        let genesis_hash = ids::Id::sha256(genesis_bytes);
+        match state.get_genesis_hash().await? {
+            Some(stored) if stored != genesis_hash => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "genesis mismatch: database was created with genesis hash {stored}, but the given genesis bytes hash to {genesis_hash}"
+                    ),
+                ));
+            }
+            Some(_) => {}
+            None => {
+                if has_last_accepted {
+                    // databases created before the hash was tracked still have their genesis block
+                    let stored_blk_id = state.get_block_id_at_height(0).await?;
+                    let genesis_blk_id = vm_state.genesis.to_block(&state.schedule.base)?.id();
+                    if stored_blk_id != genesis_blk_id {
+                        return Err(Error::new(
+                            ErrorKind::InvalidData,
+                            format!(
+                                "genesis mismatch: database has genesis block {stored_blk_id}, but the given genesis creates {genesis_blk_id}"
+                            ),
+                        ));
+                    }
+                    log::warn!("recording genesis hash {genesis_hash} for a database created before it was tracked");
+                }
+                state.put_genesis_hash(&genesis_hash).await?;
+            }
+        }
+
         if has_last_accepted {
             let last_accepted_blk_id = state.get_last_accepted_block_id().await?;
             vm_state.preferred = last_accepted_blk_id;
             log::info!("initialized Vm with last accepted block {last_accepted_blk_id}");
         } else {
-            let mut genesis_block = Block::try_new(
-                ids::Id::empty(),
-                0,
-                0,
-                vm_state.genesis.data.as_bytes().to_vec(),
-                choices::status::Status::default(),
-            )?;
+            let mut genesis_block = vm_state.genesis.to_block(&state.schedule.base)?;
             genesis_block.set_state(state.clone());
             genesis_block.accept().await?;
 
@@ -272,7 +720,21 @@ where
             log::info!("initialized Vm with genesis block {genesis_blk_id}");
         }
 
-        self.mempool = Arc::new(RwLock::new(VecDeque::with_capacity(100)));
+        // earlier versions persisted every rejected block
+        let compacting = state.clone();
+        tokio::spawn(async move {
+            match compacting.compact_rejected().await {
+                Ok(0) => {}
+                Ok(n) => log::info!("deleted {n} persisted rejected block(s)"),
+                Err(e) => log::warn!("failed to delete persisted rejected blocks {e}"),
+            }
+        });
+
+        self.mempool = Arc::new(RwLock::new(Mempool::new(
+            config.mempool_max_count,
+            config.mempool_max_bytes,
+            config.mempool_eviction_policy,
+        )));
 
         log::info!("successfully initialized Vm");
         Ok(())
@@ -325,6 +787,16 @@ where
                 server_addr: None,
             },
         );
+        if self.state.read().await.config.admin_api_enabled {
+            handlers.insert(
+                "/admin".to_string(),
+                HttpHandler {
+                    lock_option: LockOptions::ReadLock,
+                    handler: ChainHandler::new_admin(AdminService::new(self.clone())),
+                    server_addr: None,
+                },
+            );
+        }
 
         Ok(handlers)
     }
@@ -348,38 +820,53 @@ where
 
         let vm_state = self.state.read().await;
         if let Some(state) = &vm_state.state {
-            self.notify_block_ready().await;
-
             // "state" must have preferred block in cache/verified_block
             // otherwise, not found error from rpcchainvm database
             let prnt_blk = state.get_block(&vm_state.preferred).await?;
+            let rules = state.schedule.rules_at(prnt_blk.timestamp());
             let unix_now = Utc::now()
                 .timestamp()
                 .try_into()
                 .expect("timestamp to convert from i64 to u64");
 
-            let first = mempool.pop_front().unwrap();
-            let mut block = Block::try_new(
-                prnt_blk.id(),
-                prnt_blk.height() + 1,
-                unix_now,
-                first,
-                choices::status::Status::Processing,
-            )?;
-            block.set_state(state.clone());
-            block.verify().await?;
+            let entries = take_entries(&mut mempool, state, &prnt_blk, unix_now, &rules).await?;
+            if entries.is_empty() {
+                return Err(Error::new(ErrorKind::Other, "no pending block"));
+            }
+
+            let num_entries = entries.len();
+            let built = build_verified_block(state, &prnt_blk, unix_now, &rules, &entries).await;
+            let block = match built {
+                Ok(block) => {
+                    state.built_blocks.write().await.insert(block.id());
+                    block
+                }
+                Err(e) => {
+                    // e.g., too many processing blocks, the proposals stay pending
+                    mempool.restore_front(entries);
+                    return Err(e);
+                }
+            };
 
-            log::info!("successfully built block");
+            if !mempool.is_empty() {
+                self.notify_block_ready().await;
+            }
+
+            log::info!("successfully built block with {num_entries} entries");
             return Ok(block);
         }
 
         Err(Error::new(ErrorKind::NotFound, "state manager not found"))
     }
 
+    /// Also returns the proposals of blocks rejected since the last call to the
+    /// mempool, since the engine sets the preference after every decision.
     async fn set_preference(&self, id: ids::Id) -> io::Result<()> {
         let mut vm_state = self.state.write().await;
         vm_state.preferred = id;
+        drop(vm_state);
 
+        self.requeue_rejected().await?;
         Ok(())
     }
 
@@ -394,15 +881,28 @@ where
         ))
     }
 
-    // Passes back ok as a no-op for now.
-    // TODO: Remove after v1.11.x activates
+    /// Returns an "index incomplete" error until the height index
+    /// has been written for every accepted block.
     async fn verify_height_index(&self) -> io::Result<()> {
-        Ok(())
+        let vm_state = self.state.read().await;
+        if let Some(state) = &vm_state.state {
+            if state.is_height_index_complete().await? {
+                return Ok(());
+            }
+            return Err(subnet::rpc::errors::Error::IndexIncomplete.to_err());
+        }
+
+        Err(Error::new(ErrorKind::NotFound, "state manager not found"))
     }
 
-    // Returns an error as a no-op for now.
-    async fn get_block_id_at_height(&self, _height: u64) -> io::Result<ids::Id> {
-        Err(Error::new(ErrorKind::NotFound, "block id not found"))
+    /// Returns the accepted block Id at the given height from the height index.
+    async fn get_block_id_at_height(&self, height: u64) -> io::Result<ids::Id> {
+        let vm_state = self.state.read().await;
+        if let Some(state) = &vm_state.state {
+            return state.get_block_id_at_height(height).await;
+        }
+
+        Err(Error::new(ErrorKind::NotFound, "state manager not found"))
     }
 
     async fn state_sync_enabled(&self) -> io::Result<bool> {
@@ -417,23 +917,92 @@ where
 {
     type Block = Block;
 
+    /// Returns the bytes of the requested block followed by its ancestors,
+    /// walking parent links until genesis or any of the limits is reached.
+    /// The requested block is always included, even if it exceeds `max_block_size`.
+    /// Returns no block if the requested block is unknown.
     async fn get_ancestors(
         &self,
-        _block_id: ids::Id,
-        _max_block_num: i32,
-        _max_block_size: i32,
-        _max_block_retrival_time: Duration,
+        block_id: ids::Id,
+        max_block_num: i32,
+        max_block_size: i32,
+        max_block_retrival_time: Duration,
     ) -> io::Result<Vec<Bytes>> {
-        Err(Error::new(
-            ErrorKind::Unsupported,
-            "get_ancestors not implemented",
-        ))
+        let max_block_num = usize::try_from(max_block_num)
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, format!("max_block_num {e}")))?;
+        let max_block_size = usize::try_from(max_block_size)
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, format!("max_block_size {e}")))?;
+
+        let vm_state = self.state.read().await;
+        if let Some(state) = &vm_state.state {
+            let start = Instant::now();
+
+            let mut block = match state.get_block(&block_id).await {
+                Ok(b) => b,
+                Err(e) => {
+                    if subnet::rpc::errors::is_not_found(&e) {
+                        log::debug!("get_ancestors: unknown block {block_id}");
+                        return Ok(Vec::new());
+                    }
+                    return Err(e);
+                }
+            };
+            let mut ancestors_bytes_len = block.bytes().len() + ANCESTOR_LEN_PREFIX_BYTES;
+            let mut ancestors = Vec::with_capacity(max_block_num.min(1024));
+            ancestors.push(Bytes::from(block.bytes().to_vec()));
+
+            while ancestors.len() < max_block_num && block.height() > 0 {
+                if start.elapsed() >= max_block_retrival_time {
+                    log::debug!("get_ancestors exceeded max block retrival time");
+                    break;
+                }
+
+                block = match state.get_block(&block.parent_id()).await {
+                    Ok(b) => b,
+                    Err(e) => {
+                        log::debug!(
+                            "failed to get parent block {} during ancestors lookup: {e}",
+                            block.parent_id()
+                        );
+                        break;
+                    }
+                };
+
+                ancestors_bytes_len += block.bytes().len() + ANCESTOR_LEN_PREFIX_BYTES;
+                if ancestors_bytes_len > max_block_size {
+                    log::debug!(
+                        "get_ancestors reached maximum response size {ancestors_bytes_len}"
+                    );
+                    break;
+                }
+
+                ancestors.push(Bytes::from(block.bytes().to_vec()));
+            }
+
+            log::debug!("get_ancestors returning {} block(s)", ancestors.len());
+            return Ok(ancestors);
+        }
+
+        Err(Error::new(ErrorKind::NotFound, "state manager not found"))
     }
-    async fn batched_parse_block(&self, _blocks: &[Vec<u8>]) -> io::Result<Vec<Self::Block>> {
-        Err(Error::new(
-            ErrorKind::Unsupported,
-            "batched_parse_block not implemented",
-        ))
+
+    /// Parses the blocks in input order, holding the Vm state lock once for the whole batch.
+    /// The rpcchainvm server of the pinned `avalanche-types` (0.1.5) never calls this:
+    /// it serves `BatchedParseBlock` by calling `parse_block` once per block, so batches
+    /// only take this path once the Vm runs on a server version that forwards them here.
+    async fn batched_parse_block(&self, blocks: &[Vec<u8>]) -> io::Result<Vec<Self::Block>> {
+        let vm_state = self.state.read().await;
+        if let Some(state) = &vm_state.state {
+            let mut parsed = Vec::with_capacity(blocks.len());
+            for bytes in blocks {
+                parsed.push(Self::parse_block_with_state(state, bytes).await?);
+            }
+
+            log::debug!("batch parsed {} block(s)", parsed.len());
+            return Ok(parsed);
+        }
+
+        Err(Error::new(ErrorKind::NotFound, "state manager not found"))
     }
 }
 
@@ -472,8 +1041,12 @@ where
         Ok(())
     }
 
-    /// Currently, no app-specific messages, so returning Ok.
-    async fn app_gossip(&self, _node_id: &ids::node::Id, _msg: &[u8]) -> io::Result<()> {
+    /// Adds gossiped proposals to the mempool.
+    /// Invalid gossip is dropped rather than failing the peer message.
+    async fn app_gossip(&self, node_id: &ids::node::Id, msg: &[u8]) -> io::Result<()> {
+        if let Err(e) = self.receive_gossip(node_id, msg).await {
+            log::warn!("dropping app gossip from {node_id}: {e}");
+        }
         Ok(())
     }
 }
@@ -570,20 +1143,974 @@ where
     async fn parse_block(&self, bytes: &[u8]) -> io::Result<<Self as Parser>::Block> {
         let vm_state = self.state.read().await;
         if let Some(state) = &vm_state.state {
-            let mut new_block = Block::from_slice(bytes)?;
-            new_block.set_status(choices::status::Status::Processing);
-            new_block.set_state(state.clone());
-            log::debug!("parsed block {}", new_block.id());
-
-            match state.get_block(&new_block.id()).await {
-                Ok(prev) => {
-                    log::debug!("returning previously parsed block {}", prev.id());
-                    return Ok(prev);
-                }
-                Err(_) => return Ok(new_block),
-            };
+            return Self::parse_block_with_state(state, bytes).await;
         }
 
         Err(Error::new(ErrorKind::NotFound, "state manager not found"))
     }
 }
+
+/// `RUST_LOG=debug cargo test --package timestampvm --lib -- vm::test_get_ancestors --exact --show-output`
+#[tokio::test]
+async fn test_get_ancestors() {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let state = state::State::default();
+    let vm: Vm<()> = Vm::new();
+    vm.state.write().await.state = Some(state.clone());
+
+    let mut blks = Vec::new();
+    let mut parent_id = ids::Id::empty();
+    for height in 0..10 {
+        let mut blk = Block::try_new(
+            parent_id,
+            height,
+            height,
+            random_manager::secure_bytes(10).unwrap(),
+            choices::status::Status::default(),
+        )
+        .unwrap();
+        blk.set_state(state.clone());
+        blk.verify().await.unwrap();
+        blk.accept().await.unwrap();
+
+        parent_id = blk.id();
+        blks.push(blk);
+    }
+    let tip = blks.last().unwrap().id();
+
+    // walks all the way down to genesis, newest first
+    let ancestors = vm
+        .get_ancestors(tip, 100, i32::MAX, Duration::from_secs(10))
+        .await
+        .unwrap();
+    assert_eq!(ancestors.len(), blks.len());
+    for (got, expected) in ancestors.iter().zip(blks.iter().rev()) {
+        assert_eq!(got.as_ref(), expected.bytes());
+    }
+
+    let ancestors = vm
+        .get_ancestors(tip, 3, i32::MAX, Duration::from_secs(10))
+        .await
+        .unwrap();
+    assert_eq!(ancestors.len(), 3);
+
+    // the requested block is returned even if it alone exceeds the size limit
+    let ancestors = vm
+        .get_ancestors(tip, 100, 1, Duration::from_secs(10))
+        .await
+        .unwrap();
+    assert_eq!(ancestors.len(), 1);
+
+    // unknown blocks have no ancestors to serve
+    assert!(vm
+        .get_ancestors(ids::Id::empty(), 100, i32::MAX, Duration::from_secs(10))
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+/// `RUST_LOG=debug cargo test --package timestampvm --lib -- vm::test_batched_parse_block --exact --show-output`
+#[tokio::test]
+async fn test_batched_parse_block() {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let state = state::State::default();
+    let vm: Vm<()> = Vm::new();
+    vm.state.write().await.state = Some(state.clone());
+
+    let mut genesis_blk = Block::try_new(
+        ids::Id::empty(),
+        0,
+        0,
+        random_manager::secure_bytes(10).unwrap(),
+        choices::status::Status::default(),
+    )
+    .unwrap();
+    genesis_blk.set_state(state.clone());
+    genesis_blk.accept().await.unwrap();
+
+    let mut blk1 = Block::try_new(
+        genesis_blk.id(),
+        1,
+        1,
+        random_manager::secure_bytes(10).unwrap(),
+        choices::status::Status::default(),
+    )
+    .unwrap();
+    blk1.set_state(state.clone());
+    blk1.verify().await.unwrap();
+
+    let blk2 = Block::try_new(
+        blk1.id(),
+        2,
+        2,
+        random_manager::secure_bytes(10).unwrap(),
+        choices::status::Status::default(),
+    )
+    .unwrap();
+
+    let parsed = vm
+        .batched_parse_block(&[
+            blk2.bytes().to_vec(),
+            genesis_blk.bytes().to_vec(),
+            blk1.bytes().to_vec(),
+        ])
+        .await
+        .unwrap();
+    assert_eq!(parsed.len(), 3);
+    assert_eq!(parsed[0].id(), blk2.id());
+    assert_eq!(parsed[0].status(), choices::status::Status::Processing);
+    assert_eq!(parsed[1].id(), genesis_blk.id());
+    assert_eq!(parsed[1].status(), choices::status::Status::Accepted);
+    assert_eq!(parsed[2].id(), blk1.id());
+
+    assert!(vm.batched_parse_block(&[vec![0x1, 0x2]]).await.is_err());
+}
+
+#[cfg(test)]
+type Recorded<T> = Arc<std::sync::Mutex<Vec<T>>>;
+
+/// Records outbound app gossip, so tests can relay it between Vms.
+#[cfg(test)]
+#[derive(Clone, Default)]
+struct RecordingAppSender {
+    gossip: Recorded<Vec<u8>>,
+}
+
+#[cfg(test)]
+#[tonic::async_trait]
+impl AppSender for RecordingAppSender {
+    async fn send_app_request(
+        &self,
+        _node_ids: ids::node::Set,
+        _request_id: u32,
+        _request: Vec<u8>,
+    ) -> io::Result<()> {
+        Ok(())
+    }
+
+    async fn send_app_response(
+        &self,
+        _node_id: ids::node::Id,
+        _request_id: u32,
+        _response: Vec<u8>,
+    ) -> io::Result<()> {
+        Ok(())
+    }
+
+    async fn send_app_gossip(&self, msg: Vec<u8>) -> io::Result<()> {
+        self.gossip.lock().unwrap().push(msg);
+        Ok(())
+    }
+
+    async fn send_app_gossip_specific(
+        &self,
+        _node_ids: ids::node::Set,
+        msg: Vec<u8>,
+    ) -> io::Result<()> {
+        self.gossip.lock().unwrap().push(msg);
+        Ok(())
+    }
+
+    async fn send_cross_chain_app_request(
+        &self,
+        _chain_id: ids::Id,
+        _request_id: u32,
+        _app_request_bytes: Vec<u8>,
+    ) -> io::Result<()> {
+        Ok(())
+    }
+
+    async fn send_cross_chain_app_response(
+        &self,
+        _chain_id: ids::Id,
+        _request_id: u32,
+        _app_response_bytes: Vec<u8>,
+    ) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// `RUST_LOG=debug cargo test --package timestampvm --lib -- vm::test_gossip --exact --show-output`
+#[tokio::test]
+async fn test_gossip() {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let node_a = ids::node::Id::from_slice(&[0xa; 20]);
+
+    let sender_a = RecordingAppSender::default();
+    let mut vm_a: Vm<RecordingAppSender> = Vm::new();
+    vm_a.app_sender = Some(sender_a.clone());
+
+    let sender_b = RecordingAppSender::default();
+    let mut vm_b: Vm<RecordingAppSender> = Vm::new();
+    vm_b.app_sender = Some(sender_b.clone());
+
+    let data = random_manager::secure_bytes(10).unwrap();
+    vm_a.propose_block(data.clone(), None).await.unwrap();
+    assert_eq!(vm_a.mempool.read().await.len(), 1);
+    assert_eq!(
+        mempool::error_of(&vm_a.propose_block(data.clone(), None).await.unwrap_err()),
+        Some(mempool::Error::Duplicate)
+    );
+
+    let msg = sender_a.gossip.lock().unwrap().pop().unwrap();
+    vm_b.app_gossip(&node_a, &msg).await.unwrap();
+    assert!(vm_b.mempool.read().await.contains(&ids::Id::sha256(&data)));
+
+    // duplicates are dropped, and received gossip is not relayed
+    vm_b.app_gossip(&node_a, &msg).await.unwrap();
+    assert_eq!(vm_b.mempool.read().await.len(), 1);
+    assert!(sender_b.gossip.lock().unwrap().is_empty());
+
+    // same limits as local proposals
+    let oversized = gossip::Message::from(block::Entry::new(vec![0; PROPOSE_LIMIT_BYTES + 1]))
+        .to_vec()
+        .unwrap();
+    assert!(vm_b.receive_gossip(&node_a, &oversized).await.is_err());
+    vm_b.app_gossip(&node_a, &oversized).await.unwrap();
+    vm_b.app_gossip(&node_a, b"not json").await.unwrap();
+    assert_eq!(vm_b.mempool.read().await.len(), 1);
+
+    // signatures are checked again by each receiving node
+    let key = avalanche_types::key::secp256k1::private_key::Key::generate().unwrap();
+    let sig = ProposalSignature::sign_secp256k1(&key, &ids::Id::empty(), &[3; 10]).unwrap();
+    assert!(vm_b
+        .propose_block(vec![2; 10], Some(sig.clone()))
+        .await
+        .is_err());
+    let forged = gossip::Message::from(block::Entry::new_signed(vec![2; 10], sig.clone()))
+        .to_vec()
+        .unwrap();
+    assert!(vm_b.receive_gossip(&node_a, &forged).await.is_err());
+    let signed = gossip::Message::from(block::Entry::new_signed(vec![3; 10], sig))
+        .to_vec()
+        .unwrap();
+    vm_b.receive_gossip(&node_a, &signed).await.unwrap();
+    assert_eq!(vm_b.mempool.read().await.len(), 2);
+
+    // the limit comes from the chain config until the state is initialized
+    vm_b.state.write().await.config.propose_limit_bytes = 10;
+    let msg = gossip::Message::from(block::Entry::new(vec![1; 11]))
+        .to_vec()
+        .unwrap();
+    assert!(vm_b.receive_gossip(&node_a, &msg).await.is_err());
+    assert!(vm_b.propose_block(vec![1; 11], None).await.is_err());
+    vm_b.propose_block(vec![1; 10], None).await.unwrap();
+}
+
+/// `RUST_LOG=debug cargo test --package timestampvm --lib -- vm::test_gossip_included --exact --show-output`
+#[tokio::test]
+async fn test_gossip_included() {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let node_a = ids::node::Id::from_slice(&[0xa; 20]);
+    let node_b = ids::node::Id::from_slice(&[0xb; 20]);
+
+    // two nodes of the same chain, each with its own db
+    let mut vms = Vec::new();
+    for _ in 0..2 {
+        let state = state::State::default();
+        let mut genesis_blk = Block::try_new(
+            ids::Id::empty(),
+            0,
+            0,
+            vec![0; 10],
+            choices::status::Status::default(),
+        )
+        .unwrap();
+        genesis_blk.set_state(state.clone());
+        genesis_blk.accept().await.unwrap();
+
+        let sender = RecordingAppSender::default();
+        let mut vm: Vm<RecordingAppSender> = Vm::new();
+        vm.app_sender = Some(sender.clone());
+        {
+            let mut vm_state = vm.state.write().await;
+            vm_state.state = Some(state);
+            vm_state.preferred = genesis_blk.id();
+        }
+        vms.push((vm, sender));
+    }
+    let (vm_a, sender_a) = &vms[0];
+    let (vm_b, sender_b) = &vms[1];
+
+    // both nodes get the same proposal, and the same second one
+    let data = vec![1; 10];
+    let id = vm_a.propose_block(data.clone(), None).await.unwrap();
+    let msg = sender_a.gossip.lock().unwrap().pop().unwrap();
+    vm_b.receive_gossip(&node_a, &msg).await.unwrap();
+    vm_b.propose_block(vec![2; 10], None).await.unwrap();
+    let msg = sender_b.gossip.lock().unwrap().pop().unwrap();
+    vm_a.receive_gossip(&node_b, &msg).await.unwrap();
+
+    // node a builds the block, node b verifies it
+    let blk = vm_a.build_block().await.unwrap();
+    assert_eq!(blk.payloads(), vec![data.as_slice()]);
+    let mut blk_b = vm_b.parse_block(blk.bytes()).await.unwrap();
+    blk_b.verify().await.unwrap();
+
+    // node b neither admits nor builds with the proposal the block carries
+    assert_eq!(
+        vm_b.propose_block(data.clone(), None)
+            .await
+            .unwrap_err()
+            .kind(),
+        ErrorKind::AlreadyExists
+    );
+    let gossiped = gossip::Message::from(block::Entry::new(data.clone()))
+        .to_vec()
+        .unwrap();
+    vm_b.receive_gossip(&node_a, &gossiped).await.unwrap();
+    assert_eq!(
+        vm_b.mempool.read().await.len(),
+        2,
+        "gossiped before the block"
+    );
+    let next_b = vm_b.build_block().await.unwrap();
+    assert_eq!(next_b.payloads(), vec![[2; 10].as_slice()]);
+    assert!(!vm_b.mempool.read().await.contains(&id));
+
+    // once accepted, gossip of the proposal is dropped on arrival
+    blk_b.accept().await.unwrap();
+    vm_b.receive_gossip(&node_a, &gossiped).await.unwrap();
+    assert!(vm_b.mempool.read().await.is_empty());
+    assert_eq!(
+        vm_b.get_proposal_status(&id).await.unwrap(),
+        ProposalStatus::Accepted {
+            block_id: blk.id(),
+            height: 1
+        }
+    );
+}
+
+/// `RUST_LOG=debug cargo test --package timestampvm --lib -- vm::test_build_block --exact --show-output`
+#[tokio::test]
+#[allow(clippy::too_many_lines)]
+async fn test_build_block() {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let mut state = state::State::default();
+    let mut genesis_blk = Block::try_new(
+        ids::Id::empty(),
+        0,
+        0,
+        random_manager::secure_bytes(10).unwrap(),
+        choices::status::Status::default(),
+    )
+    .unwrap();
+    genesis_blk.set_state(state.clone());
+    genesis_blk.accept().await.unwrap();
+
+    // blocks keep the JSON encoding and a single proposal until binary blocks activate
+    let json_vm: Vm<RecordingAppSender> = Vm::new();
+    {
+        let mut vm_state = json_vm.state.write().await;
+        vm_state.state = Some(state.clone());
+        vm_state.preferred = genesis_blk.id();
+    }
+    json_vm.propose_block(vec![8; 100], None).await.unwrap();
+    json_vm.propose_block(vec![9; 100], None).await.unwrap();
+    let json_blk = json_vm.build_block().await.unwrap();
+    assert!(json_blk.is_legacy_json());
+    assert_eq!(json_blk.payloads(), vec![[8; 100].as_slice()]);
+    assert_eq!(json_vm.mempool.read().await.len(), 1);
+    state.remove_verified(&json_blk.id()).await;
+
+    // room for two 100-byte entries, but not three
+    state.schedule.base.binary_blocks = true;
+    state.schedule.base.block_size_limit = 300;
+    let vm: Vm<RecordingAppSender> = Vm::new();
+    {
+        let mut vm_state = vm.state.write().await;
+        vm_state.state = Some(state.clone());
+        vm_state.preferred = genesis_blk.id();
+    }
+    assert!(vm.build_block().await.is_err());
+
+    let mut proposal_ids = Vec::new();
+    for i in 0..5 {
+        let id = vm.propose_block(vec![i; 100], None).await.unwrap();
+        assert_eq!(id, ids::Id::sha256(vec![i; 100]));
+        assert_eq!(
+            vm.get_proposal_status(&id).await.unwrap(),
+            ProposalStatus::Pending
+        );
+        proposal_ids.push(id);
+    }
+
+    let blk1 = vm.build_block().await.unwrap();
+    assert_eq!(blk1.height(), 1);
+    assert!(blk1.bytes().len() <= 300);
+    let payloads = blk1.payloads();
+    assert_eq!(payloads.len(), 2);
+    assert_eq!(payloads[0], [0; 100].as_slice());
+    assert_eq!(vm.mempool.read().await.len(), 5 - payloads.len());
+
+    assert_eq!(
+        vm.get_proposal_status(&proposal_ids[0]).await.unwrap(),
+        ProposalStatus::Processing {
+            block_id: blk1.id()
+        }
+    );
+    assert_eq!(
+        vm.get_proposal_status(&proposal_ids[4]).await.unwrap(),
+        ProposalStatus::Pending
+    );
+
+    let mut blk1 = blk1;
+    blk1.accept().await.unwrap();
+    vm.state.write().await.preferred = blk1.id();
+
+    // proposals stay pending, in order, when the built block can't be verified
+    *state.verified_blocks.write().await = state::processing::ProcessingTree::new(0);
+    let mut before = vm.mempool.read().await.clone();
+    assert!(vm
+        .build_block()
+        .await
+        .unwrap_err()
+        .to_string()
+        .contains("blocks are already processing"));
+    let mut after = vm.mempool.read().await.clone();
+    assert_eq!(after.bytes(), before.bytes());
+    while let Some(taken) = before.pop_front() {
+        assert_eq!(after.pop_front(), Some(taken));
+    }
+    assert!(after.is_empty());
+    *state.verified_blocks.write().await = state::processing::ProcessingTree::default();
+    assert_eq!(
+        vm.get_proposal_status(&proposal_ids[0]).await.unwrap(),
+        ProposalStatus::Accepted {
+            block_id: blk1.id(),
+            height: 1
+        }
+    );
+    assert_eq!(
+        vm.get_proposal_status(&ids::Id::sha256([9; 100]))
+            .await
+            .unwrap(),
+        ProposalStatus::Dropped
+    );
+
+    // accepted proposals can't be replayed
+    assert_eq!(
+        vm.propose_block(vec![0; 100], None)
+            .await
+            .unwrap_err()
+            .kind(),
+        ErrorKind::AlreadyExists
+    );
+
+    let resp = crate::api::chain_handlers::GetProposalStatusResponse {
+        id: proposal_ids[0],
+        status: ProposalStatus::Accepted {
+            block_id: blk1.id(),
+            height: 1,
+        },
+    };
+    let encoded = serde_json::to_value(&resp).unwrap();
+    assert_eq!(encoded["status"], "accepted");
+    assert_eq!(encoded["height"], 1);
+    assert_eq!(encoded["blockId"], blk1.id().to_string());
+
+    // the mempool only admits proposals from allowed proposers, once restricted
+    let key = avalanche_types::key::secp256k1::private_key::Key::generate().unwrap();
+    let sig = ProposalSignature::sign_secp256k1(&key, &ids::Id::empty(), &[7; 10]).unwrap();
+    vm.state
+        .write()
+        .await
+        .state
+        .as_mut()
+        .unwrap()
+        .schedule
+        .base
+        .allowed_proposers = vec![sig.proposer.clone()];
+    assert!(vm
+        .propose_block(vec![8; 10], None)
+        .await
+        .unwrap_err()
+        .to_string()
+        .contains("unsigned proposals are not allowed"));
+    let other_key = avalanche_types::key::secp256k1::private_key::Key::generate().unwrap();
+    let other_sig =
+        ProposalSignature::sign_secp256k1(&other_key, &ids::Id::empty(), &[8; 10]).unwrap();
+    assert!(vm
+        .propose_block(vec![8; 10], Some(other_sig))
+        .await
+        .unwrap_err()
+        .to_string()
+        .contains("is not allowed"));
+    vm.propose_block(vec![7; 10], Some(sig)).await.unwrap();
+}
+
+/// `RUST_LOG=debug cargo test --package timestampvm --lib -- vm::test_genesis_mismatch --exact --show-output`
+#[tokio::test]
+async fn test_genesis_mismatch() {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let db = subnet::rpc::database::memdb::Database::new_boxed();
+    let genesis_a = Genesis::default().to_vec().unwrap();
+    let genesis_b = Genesis {
+        data: String::from("another chain"),
+        ..Default::default()
+    }
+    .to_vec()
+    .unwrap();
+
+    let (to_engine, _rx) = tokio::sync::mpsc::channel(1);
+    let mut vm: Vm<RecordingAppSender> = Vm::new();
+    vm.initialize(
+        None,
+        db.clone(),
+        &genesis_a,
+        &[],
+        &[],
+        to_engine.clone(),
+        &[],
+        RecordingAppSender::default(),
+    )
+    .await
+    .unwrap();
+    let genesis_blk_id = vm.last_accepted().await.unwrap();
+
+    // restarting with the same genesis keeps the chain
+    let mut vm: Vm<RecordingAppSender> = Vm::new();
+    vm.initialize(
+        None,
+        db.clone(),
+        &genesis_a,
+        &[],
+        &[],
+        to_engine.clone(),
+        &[],
+        RecordingAppSender::default(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(vm.last_accepted().await.unwrap(), genesis_blk_id);
+
+    let mut vm: Vm<RecordingAppSender> = Vm::new();
+    let err = vm
+        .initialize(
+            None,
+            db.clone(),
+            &genesis_b,
+            &[],
+            &[],
+            to_engine.clone(),
+            &[],
+            RecordingAppSender::default(),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert!(err.to_string().contains("genesis mismatch"));
+
+    // databases created before the genesis hash was tracked are checked against their genesis block
+    let legacy_db = subnet::rpc::database::memdb::Database::new_boxed();
+    let legacy_state = state::State {
+        db: Arc::new(RwLock::new(legacy_db.clone())),
+        ..Default::default()
+    };
+    let mut legacy_genesis_blk = Genesis::default().to_block(&Rules::default()).unwrap();
+    legacy_genesis_blk.set_state(legacy_state.clone());
+    legacy_genesis_blk.accept().await.unwrap();
+    assert!(legacy_state.get_genesis_hash().await.unwrap().is_none());
+
+    let mut vm: Vm<RecordingAppSender> = Vm::new();
+    let err = vm
+        .initialize(
+            None,
+            legacy_db.clone(),
+            &genesis_b,
+            &[],
+            &[],
+            to_engine.clone(),
+            &[],
+            RecordingAppSender::default(),
+        )
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("genesis mismatch"));
+    assert!(legacy_state.get_genesis_hash().await.unwrap().is_none());
+
+    let mut vm: Vm<RecordingAppSender> = Vm::new();
+    vm.initialize(
+        None,
+        legacy_db,
+        &genesis_a,
+        &[],
+        &[],
+        to_engine,
+        &[],
+        RecordingAppSender::default(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(vm.last_accepted().await.unwrap(), legacy_genesis_blk.id());
+    assert_eq!(
+        legacy_state.get_genesis_hash().await.unwrap(),
+        Some(ids::Id::sha256(&genesis_a))
+    );
+}
+
+/// `RUST_LOG=debug cargo test --package timestampvm --lib -- vm::test_inclusion_proof --exact --show-output`
+#[tokio::test]
+async fn test_inclusion_proof() {
+    use crate::{
+        api::chain_handlers::{GetInclusionProofArgs, Rpc},
+        client,
+    };
+
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let mut state = state::State::default();
+    state.schedule.base.binary_blocks = true;
+    state.chain_id = ids::Id::from_slice(&[7; 32]);
+    let mut genesis_blk = Block::from_slice(
+        br#"{"parent_id":"11111111111111111111111111111111LpoYY","height":0,"timestamp":0,"data":"0x0102"}"#,
+    )
+    .unwrap();
+    genesis_blk.set_state(state.clone());
+    genesis_blk.accept().await.unwrap();
+
+    let vm: Vm<RecordingAppSender> = Vm::new();
+    {
+        let mut vm_state = vm.state.write().await;
+        vm_state.state = Some(state.clone());
+        vm_state.preferred = genesis_blk.id();
+    }
+
+    let key = avalanche_types::key::secp256k1::private_key::Key::generate().unwrap();
+    let sig = ProposalSignature::sign_secp256k1(&key, &state.chain_id, &[1; 10]).unwrap();
+    vm.propose_block(vec![0; 10], None).await.unwrap();
+    vm.propose_block(vec![1; 10], Some(sig)).await.unwrap();
+    vm.propose_block(vec![2; 10], None).await.unwrap();
+    let mut blk = vm.build_block().await.unwrap();
+    blk.accept().await.unwrap();
+    assert_eq!(blk.entries().len(), 3);
+
+    let service = ChainService::new(vm.clone());
+    let args = |entry_index: Option<u32>, data_hash: Option<ids::Id>| GetInclusionProofArgs {
+        block_id: blk.id().to_string(),
+        entry_index,
+        data_hash: data_hash.map(|h| h.to_string()),
+    };
+
+    for i in 0..3_u8 {
+        let data = vec![i; 10];
+        let proof = service
+            .get_inclusion_proof(args(Some(u32::from(i)), None))
+            .await
+            .unwrap();
+        assert_eq!(proof.entry_count, 3);
+        assert_eq!(proof.signature.is_some(), i == 1);
+        client::verify_inclusion_proof(&state.chain_id, &data, &proof).unwrap();
+        // signatures only hold for the chain they were made for
+        assert_eq!(
+            client::verify_inclusion_proof(&ids::Id::empty(), &data, &proof).is_ok(),
+            i != 1
+        );
+
+        let by_hash = service
+            .get_inclusion_proof(args(None, Some(ids::Id::sha256(&data))))
+            .await
+            .unwrap();
+        assert_eq!(by_hash, proof);
+
+        // wrong data, wrong position, and a forged block Id
+        assert!(client::verify_inclusion_proof(&state.chain_id, &[9; 10], &proof).is_err());
+        let mut moved = proof.clone();
+        moved.entry_index = (moved.entry_index + 1) % 3;
+        assert!(client::verify_inclusion_proof(&state.chain_id, &data, &moved).is_err());
+        let mut forged = proof.clone();
+        forged.block_id = genesis_blk.id();
+        assert!(client::verify_inclusion_proof(&state.chain_id, &data, &forged).is_err());
+    }
+
+    assert!(service
+        .get_inclusion_proof(args(Some(3), None))
+        .await
+        .is_err());
+    assert!(service
+        .get_inclusion_proof(args(None, Some(ids::Id::sha256([9; 10]))))
+        .await
+        .is_err());
+    assert!(service.get_inclusion_proof(args(None, None)).await.is_err());
+
+    // the Id of blocks encoded as JSON does not cover a root
+    let legacy = GetInclusionProofArgs {
+        block_id: genesis_blk.id().to_string(),
+        entry_index: Some(0),
+        data_hash: None,
+    };
+    assert!(service.get_inclusion_proof(legacy).await.is_err());
+
+    // the entry index is also accepted as a string, as sent by the client
+    let args: GetInclusionProofArgs =
+        serde_json::from_str(&format!(r#"{{"blockId":"{}","entryIndex":"2"}}"#, blk.id())).unwrap();
+    assert_eq!(args.entry_index, Some(2));
+}
+
+/// `RUST_LOG=debug cargo test --package timestampvm --lib -- vm::test_get_blocks --exact --show-output`
+#[tokio::test]
+async fn test_get_blocks() {
+    use crate::api::chain_handlers::{
+        GetBlockByHeightArgs, GetBlocksArgs, Rpc, MAX_BLOCKS_PER_PAGE,
+    };
+
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let mut state = state::State::default();
+    let vm: Vm<RecordingAppSender> = Vm::new();
+    vm.state.write().await.state = Some(state.clone());
+
+    let mut blks = Vec::new();
+    let mut parent_id = ids::Id::empty();
+    for height in 0..(u64::from(MAX_BLOCKS_PER_PAGE) + 5) {
+        let mut blk = Block::try_new(
+            parent_id,
+            height,
+            height,
+            random_manager::secure_bytes(10).unwrap(),
+            choices::status::Status::default(),
+        )
+        .unwrap();
+        blk.set_state(state.clone());
+        blk.verify().await.unwrap();
+        blk.accept().await.unwrap();
+
+        parent_id = blk.id();
+        blks.push(blk);
+    }
+    state.repair_height_index().await.unwrap();
+
+    let service = ChainService::new(vm.clone());
+    let resp = service
+        .get_block_by_height(GetBlockByHeightArgs { height: 3 })
+        .await
+        .unwrap();
+    assert_eq!(resp.id, blks[3].id());
+    assert_eq!(resp.block.height(), 3);
+    assert!(service
+        .get_block_by_height(GetBlockByHeightArgs {
+            height: blks.len() as u64
+        })
+        .await
+        .is_err());
+
+    let page = service
+        .get_blocks(GetBlocksArgs {
+            start_height: 2,
+            limit: Some(3),
+        })
+        .await
+        .unwrap();
+    let ids: Vec<ids::Id> = page.blocks.iter().map(|b| b.id).collect();
+    assert_eq!(ids, blks[2..5].iter().map(Block::id).collect::<Vec<_>>());
+    assert_eq!(page.next_height, Some(5));
+
+    // the page size is capped, and the last page has no next height
+    let page = service
+        .get_blocks(GetBlocksArgs {
+            start_height: 0,
+            limit: None,
+        })
+        .await
+        .unwrap();
+    assert_eq!(page.blocks.len(), MAX_BLOCKS_PER_PAGE as usize);
+    let page = service
+        .get_blocks(GetBlocksArgs {
+            start_height: page.next_height.unwrap(),
+            limit: Some(u32::MAX),
+        })
+        .await
+        .unwrap();
+    assert_eq!(page.blocks.len(), 5);
+    assert_eq!(page.blocks.last().unwrap().id, blks.last().unwrap().id());
+    assert!(page.next_height.is_none());
+
+    let page = service
+        .get_blocks(GetBlocksArgs {
+            start_height: blks.len() as u64,
+            limit: None,
+        })
+        .await
+        .unwrap();
+    assert!(page.blocks.is_empty());
+    assert!(page.next_height.is_none());
+    assert!(service
+        .get_blocks(GetBlocksArgs {
+            start_height: 0,
+            limit: Some(0),
+        })
+        .await
+        .is_err());
+
+    // heights are also accepted as strings, as sent by the client
+    let args: GetBlocksArgs = serde_json::from_str(r#"{"startHeight":"7","limit":"2"}"#).unwrap();
+    assert_eq!((args.start_height, args.limit), (7, Some(2)));
+}
+
+/// `RUST_LOG=debug cargo test --package timestampvm --lib -- vm::test_requeue_rejected --exact --show-output`
+#[tokio::test]
+#[allow(clippy::too_many_lines)]
+async fn test_requeue_rejected() {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let mut state = state::State::default();
+    state.schedule.base.binary_blocks = true;
+    let mut genesis_blk = Block::try_new(
+        ids::Id::empty(),
+        0,
+        0,
+        random_manager::secure_bytes(10).unwrap(),
+        choices::status::Status::default(),
+    )
+    .unwrap();
+    genesis_blk.set_state(state.clone());
+    genesis_blk.accept().await.unwrap();
+
+    let vm: Vm<RecordingAppSender> = Vm::new();
+    {
+        let mut vm_state = vm.state.write().await;
+        vm_state.state = Some(state.clone());
+        vm_state.preferred = genesis_blk.id();
+    }
+    let max_requeues = vm.state.read().await.config.max_requeues;
+
+    let a = vm.propose_block(vec![1; 10], None).await.unwrap();
+    let b = vm.propose_block(vec![2; 10], None).await.unwrap();
+
+    // returned after each rejection, up to the limit
+    for round in 1..=max_requeues {
+        let mut blk = vm.build_block().await.unwrap();
+        assert_eq!(blk.payloads().len(), 2);
+        assert!(vm.mempool.read().await.is_empty());
+        blk.reject().await.unwrap();
+        vm.set_preference(genesis_blk.id()).await.unwrap();
+
+        assert_eq!(
+            vm.get_proposal_status(&a).await.unwrap(),
+            ProposalStatus::Pending
+        );
+        assert_eq!(vm.mempool.read().await.len(), 2);
+        assert_eq!(
+            state.metrics.snapshot().requeued_proposals,
+            u64::from(round) * 2
+        );
+    }
+
+    let mut blk = vm.build_block().await.unwrap();
+    blk.reject().await.unwrap();
+    assert_eq!(vm.requeue_rejected().await.unwrap(), 0);
+    assert!(vm.mempool.read().await.is_empty());
+    assert_eq!(
+        vm.get_proposal_status(&b).await.unwrap(),
+        ProposalStatus::Dropped
+    );
+    let snapshot = state.metrics.snapshot();
+    assert_eq!(snapshot.rejected_blocks, u64::from(max_requeues) + 1);
+    assert_eq!(snapshot.requeue_dropped_proposals, 2);
+    assert!(vm.requeues.read().await.is_empty());
+
+    // data a conflicting block already got accepted with stays out
+    vm.propose_block(vec![3; 10], None).await.unwrap();
+    vm.propose_block(vec![4; 10], None).await.unwrap();
+    let mut built = vm.build_block().await.unwrap();
+    let mut accepted = Block::try_new_with_entries(
+        genesis_blk.id(),
+        1,
+        built.timestamp(),
+        vec![block::Entry::new(vec![3; 10])],
+        choices::status::Status::Processing,
+    )
+    .unwrap();
+    accepted.set_state(state.clone());
+    accepted.verify().await.unwrap();
+    accepted.accept().await.unwrap();
+    built.reject().await.unwrap();
+    vm.set_preference(accepted.id()).await.unwrap();
+    assert!(vm.mempool.read().await.contains(&ids::Id::sha256([4; 10])));
+    assert!(!vm.mempool.read().await.contains(&ids::Id::sha256([3; 10])));
+    assert_eq!(state.metrics.snapshot().requeue_skipped_proposals, 1);
+
+    // blocks of other nodes don't bring their proposals into the mempool
+    let mut peer_blk = Block::try_new_with_entries(
+        accepted.id(),
+        2,
+        accepted.timestamp(),
+        vec![block::Entry::new(vec![5; 10])],
+        choices::status::Status::Processing,
+    )
+    .unwrap();
+    peer_blk.set_state(state.clone());
+    peer_blk.verify().await.unwrap();
+    peer_blk.reject().await.unwrap();
+    assert_eq!(vm.requeue_rejected().await.unwrap(), 0);
+    assert!(!vm.mempool.read().await.contains(&ids::Id::sha256([5; 10])));
+
+    // proposals the current rules no longer admit are dropped
+    let mut built = vm.build_block().await.unwrap();
+    assert_eq!(built.payloads(), vec![[4; 10].as_slice()]);
+    vm.state
+        .write()
+        .await
+        .state
+        .as_mut()
+        .unwrap()
+        .schedule
+        .base
+        .propose_limit_bytes = 5;
+    built.reject().await.unwrap();
+    assert_eq!(vm.requeue_rejected().await.unwrap(), 0);
+    assert!(vm.mempool.read().await.is_empty());
+    assert_eq!(state.metrics.snapshot().requeue_dropped_proposals, 3);
+    assert!(state.built_blocks.read().await.is_empty());
+}
+
+/// `RUST_LOG=debug cargo test --package timestampvm --lib -- vm::test_admin_handlers_opt_in --exact --show-output`
+#[tokio::test]
+async fn test_admin_handlers_opt_in() {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+
+    let mut vm: Vm<RecordingAppSender> = Vm::new();
+    let handlers = vm.create_handlers().await.unwrap();
+    assert!(handlers.contains_key("/rpc"));
+    assert!(!handlers.contains_key("/admin"), "off by default");
+
+    vm.state.write().await.config.admin_api_enabled = true;
+    let handlers = vm.create_handlers().await.unwrap();
+    assert!(handlers.contains_key("/admin"));
+}